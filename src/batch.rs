@@ -0,0 +1,356 @@
+use actix_web::actix;
+use actix_web::http::{Method, StatusCode, Version};
+use actix_web::http::header::HeaderMap;
+use ansi_term::Colour::{Blue, Red};
+use atty::{self, Stream as TtyStream};
+use bytes::Bytes;
+use clap::{App as ClapApp, AppSettings, Arg};
+use console::Term;
+use futures::{future, stream, Future, Stream};
+use serde_json::{self, Map, Value};
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use color::{ColorDepth, color_depth};
+use errors::ChromeError;
+use files::read_to_string;
+use format::{FormatOptions, headers_object};
+use output::{PagingMode, PrintOptions, Printer, format_version};
+use request::RequestSpec;
+use response::{Response, ResponseOptions, process_response};
+use timing::Timing;
+
+/// One line of the batch file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BatchLine {
+    #[serde(default = "default_method")]
+    method: String,
+    url: String,
+    /// In the same syntax as on the command line, e.g. `name=chrome` or `Accept:text/html`
+    #[serde(default)]
+    items: Vec<String>,
+}
+
+fn default_method() -> String {
+    String::from("GET")
+}
+
+/// A line of the batch file, ready to be sent unless it was invalid
+struct Job {
+    line: usize,
+    method: String,
+    url: String,
+    request: Result<RequestSpec, String>,
+}
+
+/// A response, with the whole body already read
+struct Exchange {
+    version: Version,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    timing: Timing,
+}
+
+struct Finished {
+    line: usize,
+    method: String,
+    url: String,
+    outcome: Result<Exchange, String>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum OutputFormat {
+    JsonLines,
+    Pretty,
+}
+
+#[derive(Default)]
+struct Summary {
+    succeeded: usize,
+    /// The line, request and what went wrong, for requests which failed or got an
+    /// unsuccessful status
+    failures: Vec<(usize, String, String, String)>,
+}
+
+/// `chrome batch FILE` sends the requests described by each line of a JSON Lines file.
+/// Returns whether all of them succeeded.
+pub fn batch(args: Vec<String>) -> Result<bool, ChromeError> {
+    let interactive = atty::is(TtyStream::Stdout);
+    let matches = ClapApp::new("chrome batch")
+        .about("Send the requests described by a JSON Lines file")
+        .global_setting(if interactive { AppSettings::ColoredHelp } else { AppSettings::ColorNever })
+        .after_help(include_str!("./help/batch.help.txt"))
+        .arg(Arg::with_name("FILE")
+             .help("The JSON Lines file with one request per line, or - for stdin")
+             .required(true)
+        )
+        .arg(Arg::with_name("concurrency")
+             .short("c")
+             .long("concurrency")
+             .takes_value(true)
+             .value_name("N")
+             .default_value("4")
+             .validator(|v| match v.parse::<usize>() {
+                 Ok(n) if n > 0 => Ok(()),
+                 _ => Err(String::from("Expected a number greater than 0")),
+             })
+             .help("How many requests to have in flight at once")
+        )
+        .arg(Arg::with_name("order")
+             .long("order")
+             .takes_value(true)
+             .value_name("ORDER")
+             .possible_values(&["input", "completion"])
+             .default_value("input")
+             .help("Print the results in the order of the file, or as soon as they are done")
+        )
+        .arg(Arg::with_name("output")
+             .long("output")
+             .short("o")
+             .takes_value(true)
+             .value_name("FORMAT")
+             .possible_values(&["jsonl", "pretty"])
+             .help("Print one JSON object per result, or each response as chrome would \
+                    [default: pretty to a terminal, jsonl otherwise]")
+        )
+        .get_matches_from(args);
+
+    let output = match matches.value_of("output") {
+        Some("jsonl") => OutputFormat::JsonLines,
+        Some(_) => OutputFormat::Pretty,
+        None if interactive => OutputFormat::Pretty,
+        None => OutputFormat::JsonLines,
+    };
+    // Due to validation, we know this can be parsed
+    let concurrency: usize = matches.value_of("concurrency").unwrap().parse().unwrap();
+    let in_order = matches.value_of("order") != Some("completion");
+
+    let jobs = read_to_string(matches.value_of("FILE").unwrap())?
+        .lines()
+        .enumerate()
+        .filter(|&(_, text)| !text.trim().is_empty())
+        .map(|(i, text)| job(i + 1, text))
+        .collect::<Vec<_>>();
+
+    let color_depth = color_depth(None, interactive);
+    let printer = Printer::new(PrintOptions {
+        colored_output: color_depth != ColorDepth::None,
+        color_depth: color_depth,
+        wrap_width: if interactive { Some(Term::stdout().size().1 as usize) } else { None },
+        format_options: FormatOptions::default(),
+    });
+    let summary = Rc::new(RefCell::new(Summary::default()));
+
+    let sent = stream::iter_ok(jobs).map(send);
+    let results: Box<Stream<Item = Finished, Error = ChromeError>> = if in_order {
+        Box::new(sent.buffered(concurrency))
+    } else {
+        Box::new(sent.buffer_unordered(concurrency))
+    };
+    let shared = summary.clone();
+    let printed = results.for_each(move |finished| -> Box<Future<Item = (), Error = ChromeError>> {
+        let shared = shared.clone();
+        match output {
+            OutputFormat::JsonLines => {
+                let failure = match finished.outcome {
+                    Ok(ref exchange) => status_failure(exchange.status),
+                    Err(ref error) => Some(error.clone()),
+                };
+                shared.borrow_mut().add(finished.line, &finished.method, &finished.url, failure);
+                Box::new(future::result(write_line(&json_line(&finished).to_string())))
+            },
+            OutputFormat::Pretty => print_pretty(finished, &printer, interactive, shared),
+        }
+    });
+
+    let mut sys = actix::System::new("chrome batch");
+    match sys.block_on(printed) {
+        // Whatever reads the results, e.g. head, has seen enough
+        Err(ChromeError::IoError { ref error }) if error.kind() == io::ErrorKind::BrokenPipe => (),
+        Err(e) => return Err(e),
+        Ok(()) => (),
+    }
+
+    let summary = summary.borrow();
+    summary.print(interactive && atty::is(TtyStream::Stderr))?;
+    Ok(summary.failures.is_empty())
+}
+
+fn job(line: usize, text: &str) -> Job {
+    let parsed = match serde_json::from_str::<BatchLine>(text) {
+        Ok(parsed) => parsed,
+        Err(e) => return Job {
+            line: line,
+            method: String::new(),
+            url: String::new(),
+            request: Err(format!("Invalid JSON: {}", e)),
+        },
+    };
+    let request = parsed.method.to_uppercase().parse::<Method>()
+        .map_err(|e| e.to_string())
+        .and_then(|method| {
+            parsed.items.iter().fold(Ok(RequestSpec::new(method, &parsed.url)), |spec, item| {
+                spec.and_then(|spec| spec.item(item).map_err(|e| e.to_string()))
+            })
+        });
+    Job { line: line, method: parsed.method.to_uppercase(), url: parsed.url, request: request }
+}
+
+/// Never fails, whatever went wrong is in the outcome
+fn send(job: Job) -> Box<Future<Item = Finished, Error = ChromeError>> {
+    let Job { line, method, url, request } = job;
+    let finished = move |outcome| Finished {
+        line: line,
+        method: method,
+        url: url,
+        outcome: outcome,
+    };
+    let spec = match request {
+        Ok(spec) => spec,
+        Err(e) => return Box::new(future::ok(finished(Err(e)))),
+    };
+    let prepared = match spec.prepare(&FormatOptions::default()) {
+        Ok(prepared) => prepared,
+        Err(e) => return Box::new(future::ok(finished(Err(e.to_string())))),
+    };
+    Box::new(prepared.send(&spec.retry)
+        .and_then(|(response, timing)| {
+            let Response { version, status, headers, body } = response;
+            body.map(move |body| Exchange {
+                version: version,
+                status: status,
+                headers: headers,
+                body: body,
+                timing: timing,
+            })
+        })
+        .then(move |outcome| Ok(finished(outcome.map_err(|e| e.to_string())))))
+}
+
+fn json_line(finished: &Finished) -> Value {
+    let mut result = Map::new();
+    result.insert(String::from("line"), Value::from(finished.line));
+    // Both are unknown for lines which aren't valid JSON
+    let or_null = |s: &str| if s.is_empty() { Value::Null } else { Value::from(s) };
+    result.insert(String::from("method"), or_null(&finished.method));
+    result.insert(String::from("url"), or_null(&finished.url));
+    match finished.outcome {
+        Ok(ref exchange) => {
+            result.insert(String::from("status"), Value::from(exchange.status.as_u16()));
+            result.insert(String::from("version"), Value::from(format_version(exchange.version)));
+            result.insert(String::from("headers"), Value::Object(headers_object(&exchange.headers)));
+            // JSON bodies are embedded as they are, so that they can be queried with e.g. jq
+            let body = serde_json::from_slice(&exchange.body)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&exchange.body).into_owned()));
+            result.insert(String::from("body"), body);
+            result.insert(String::from("timing"), exchange.timing.to_json());
+        },
+        Err(ref error) => {
+            result.insert(String::from("error"), Value::from(error.as_str()));
+        },
+    }
+    Value::Object(result)
+}
+
+/// Writes to a locked stdout, as `println!` panics once stdout is closed
+fn write_line(text: &str) -> Result<(), ChromeError> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    writeln!(out, "{}", text)?;
+    Ok(())
+}
+
+/// Prints a heading for the request, followed by the response as chrome would print it
+fn print_pretty(finished: Finished, printer: &Printer, interactive: bool, summary: Rc<RefCell<Summary>>)
+    -> Box<Future<Item = (), Error = ChromeError>> {
+    let heading = format!("#{} {} {}", finished.line, finished.method, finished.url);
+    let colored = printer.options.colored_output;
+    if let Err(e) = write_line(&if colored { Blue.bold().paint(heading).to_string() } else { heading }) {
+        return Box::new(future::err(e));
+    }
+
+    let exchange = match finished.outcome {
+        Ok(exchange) => exchange,
+        Err(error) => {
+            let written = write_line(&format!("{}\n", if colored { Red.paint(error.as_str()).to_string() } else { error.clone() }));
+            summary.borrow_mut().add(finished.line, &finished.method, &finished.url, Some(error));
+            return Box::new(future::result(written));
+        },
+    };
+
+    let options = ResponseOptions {
+        interactive_output: interactive,
+        term_width: Term::stdout().size().1 as usize,
+        term_height: Term::stdout().size().0 as usize,
+        // Shows the status and headers even when stdout isn't a terminal
+        verbose: true,
+        response_charset: None,
+        filter: None,
+        timing: None,
+        paging_mode: PagingMode::Never,
+        expectations: Vec::new(),
+        schema: None,
+    };
+    let status = exchange.status;
+    let response = Response {
+        version: exchange.version,
+        status: exchange.status,
+        headers: exchange.headers,
+        body: Box::new(future::ok(exchange.body)),
+    };
+    let (line, method, url) = (finished.line, finished.method, finished.url);
+    Box::new(process_response(printer, &options, response, exchange.timing).then(move |printed| {
+        let failure = match printed {
+            Err(ChromeError::IoError { ref error }) if error.kind() == io::ErrorKind::BrokenPipe =>
+                return Err(ChromeError::from(io::Error::from(io::ErrorKind::BrokenPipe))),
+            Ok(_) => status_failure(status),
+            Err(e) => Some(e.to_string()),
+        };
+        summary.borrow_mut().add(line, &method, &url, failure);
+        write_line("")
+    }))
+}
+
+fn status_failure(status: StatusCode) -> Option<String> {
+    if status.is_success() {
+        None
+    } else {
+        Some(format!("{} {}", status.as_u16(), status.canonical_reason().unwrap_or("")))
+    }
+}
+
+impl Summary {
+    fn add(&mut self, line: usize, method: &str, url: &str, failure: Option<String>) {
+        match failure {
+            None => self.succeeded += 1,
+            Some(reason) => self.failures.push((line, String::from(method), String::from(url), reason)),
+        }
+    }
+
+    /// Printed to stderr so that it doesn't end up in piped results
+    fn print(&self, colored: bool) -> io::Result<()> {
+        let stderr = io::stderr();
+        let mut out = stderr.lock();
+        let total = self.succeeded + self.failures.len();
+        writeln!(out, "{} {}: {} succeeded, {} failed", total, if total == 1 { "request" } else { "requests" },
+                 self.succeeded, self.failures.len())?;
+
+        let mut failures = self.failures.iter().collect::<Vec<_>>();
+        failures.sort_by_key(|&&(line, _, _, _)| line);
+        for &&(line, ref method, ref url, ref reason) in &failures {
+            // Only the first line of multi-line errors such as item parse errors
+            let reason = reason.lines().next().unwrap_or("");
+            let text = if method.is_empty() {
+                format!("  line {}: {}", line, reason)
+            } else {
+                format!("  line {}: {} {}: {}", line, method, url, reason)
+            };
+            writeln!(out, "{}", if colored { Red.paint(text).to_string() } else { text })?;
+        }
+        Ok(())
+    }
+}
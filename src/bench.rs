@@ -0,0 +1,338 @@
+use actix_web::{actix, HttpMessage};
+use ansi_term::Colour::{Blue, Green, Red};
+use atty::{self, Stream};
+use clap::{App as ClapApp, AppSettings, Arg};
+use futures::future::{self, Either, Future, Loop};
+use serde_json::{Map, Number, Value};
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use cli::{body_type, body_type_args, http_version, http_version_args};
+use connection::HttpVersion;
+use errors::ChromeError;
+use format::FormatOptions;
+use request::{PreparedRequest, RequestSpec};
+use request_item::{RequestItem, get_request_item, is_request_item};
+use response::MAX_BODY_SIZE;
+use timing::{is_duration, millis, parse_duration};
+
+static HISTOGRAM_BUCKETS: usize = 10;
+static HISTOGRAM_WIDTH: usize = 40;
+
+/// What the requests of a run got back
+#[derive(Default)]
+struct Stats {
+    latencies: Vec<Duration>,
+    statuses: BTreeMap<u16, usize>,
+    errors: BTreeMap<String, usize>,
+    bytes: usize,
+}
+
+/// Shared by the workers of a run, which all live on the same thread
+struct Run {
+    request: PreparedRequest,
+    /// Requests left to start, unlimited with only a duration
+    remaining: Cell<Option<u64>>,
+    deadline: Option<Instant>,
+    stats: RefCell<Stats>,
+}
+
+impl Run {
+    fn claim(&self) -> bool {
+        if self.deadline.map(|deadline| Instant::now() >= deadline).unwrap_or(false) {
+            return false;
+        }
+        match self.remaining.get() {
+            Some(0) => false,
+            Some(n) => {
+                self.remaining.set(Some(n - 1));
+                true
+            },
+            None => true,
+        }
+    }
+}
+
+/// `chrome bench` sends a request over and over from several concurrent workers, and
+/// reports how fast the server answered. Returns whether every request got a response.
+pub fn bench(args: Vec<String>) -> Result<bool, ChromeError> {
+    let colored = atty::is(Stream::Stdout);
+    let matches = ClapApp::new("chrome bench")
+        .about("Send a request many times concurrently and report throughput and latency")
+        .global_setting(if colored { AppSettings::ColoredHelp } else { AppSettings::ColorNever })
+        .arg(Arg::with_name("METHOD")
+             .help("The HTTP method to be used for the request")
+             .required(true)
+             .index(1)
+        )
+        .arg(Arg::with_name("URL")
+             .help("The URL for the request")
+             .required(true)
+             .index(2)
+        )
+        .arg(Arg::with_name("REQUEST_ITEM")
+             .help("Headers, URL parameters and body fields, as for a single request")
+             .multiple(true)
+             .validator(is_request_item)
+             .index(3)
+        )
+        .arg(Arg::with_name("requests")
+             .short("n")
+             .long("requests")
+             .takes_value(true)
+             .value_name("N")
+             .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|_| String::from("Expected a number")))
+             .help("How many requests to send [default: 200, or unlimited with --duration]")
+        )
+        .arg(Arg::with_name("concurrency")
+             .short("c")
+             .long("concurrency")
+             .takes_value(true)
+             .value_name("N")
+             .default_value("50")
+             .validator(|v| match v.parse::<usize>() {
+                 Ok(n) if n > 0 => Ok(()),
+                 _ => Err(String::from("Expected a number greater than 0")),
+             })
+             .help("How many requests to have in flight at once")
+        )
+        .arg(Arg::with_name("duration")
+             .long("duration")
+             .takes_value(true)
+             .value_name("DURATION")
+             .validator(is_duration)
+             .help("Stop starting new requests after this long, e.g. 30s")
+        )
+        .arg(Arg::with_name("report")
+             .long("report")
+             .takes_value(true)
+             .value_name("FORMAT")
+             .possible_values(&["table", "json"])
+             .default_value("table")
+             .help("How to print the results")
+        )
+        .args(&body_type_args())
+        // Only taken to be turned down, rather than being reported as unknown
+        .args(&http_version_args().into_iter().map(|arg| arg.hidden(true)).collect::<Vec<_>>())
+        .get_matches_from(args);
+
+    let method = matches.value_of("METHOD").unwrap().to_uppercase().parse()?;
+    let items: Vec<RequestItem> = matches.values_of("REQUEST_ITEM")
+        .map(|values| values
+            .map(|item| get_request_item(String::from(item)))
+            .map(Result::unwrap)  // Due to validation, we know these can be parsed
+            .collect())
+        .unwrap_or_else(Vec::new);
+    let spec = RequestSpec::new(method, matches.value_of("URL").unwrap())
+        .body_type(body_type(&matches))
+        .http_version(http_version(&matches))
+        .items(items);
+    // Every request goes through actix's connection pool, which only speaks HTTP/1.1 over TCP
+    if spec.unix_socket.is_some() {
+        return Err(ChromeError::ConnectionError {
+            message: String::from("chrome bench doesn't support unix sockets"),
+        });
+    }
+    if spec.http_version != HttpVersion::Http11 {
+        return Err(ChromeError::ConnectionError {
+            message: String::from("chrome bench only speaks HTTP/1.1, --http2 and --http2-prior-knowledge aren't supported"),
+        });
+    }
+    let request = spec.prepare(&FormatOptions::default())?;
+
+    // Due to validation, we know these can be parsed
+    let duration = matches.value_of("duration").map(|v| parse_duration(v).unwrap());
+    let requests = match (matches.value_of("requests"), duration) {
+        (Some(n), _) => Some(n.parse().unwrap()),
+        (None, Some(_)) => None,
+        (None, None) => Some(200),
+    };
+    let concurrency: usize = matches.value_of("concurrency").unwrap().parse().unwrap();
+
+    let started = Instant::now();
+    let run = Rc::new(Run {
+        request: request,
+        remaining: Cell::new(requests),
+        deadline: duration.map(|duration| started + duration),
+        stats: RefCell::new(Stats::default()),
+    });
+
+    let mut sys = actix::System::new("chrome bench");
+    let workers = (0..concurrency).map(|_| worker(run.clone())).collect::<Vec<_>>();
+    let _ = sys.block_on(future::join_all(workers));
+    let elapsed = started.elapsed();
+
+    let stats = run.stats.borrow();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match matches.value_of("report") {
+        Some("json") => writeln!(out, "{}", json_report(&stats, elapsed))?,
+        _ => print_table(&mut out, &stats, elapsed, colored)?,
+    }
+    Ok(stats.errors.is_empty())
+}
+
+/// Keeps sending requests one after the other until the run is over
+fn worker(run: Rc<Run>) -> impl Future<Item = (), Error = ()> {
+    future::loop_fn((), move |_| {
+        if !run.claim() {
+            return Either::A(future::ok(Loop::Break(())));
+        }
+        let run = run.clone();
+        let started = Instant::now();
+        Either::B(send(run.request.clone()).then(move |result| {
+            let mut stats = run.stats.borrow_mut();
+            match result {
+                Ok((status, size)) => {
+                    stats.latencies.push(started.elapsed());
+                    *stats.statuses.entry(status).or_insert(0) += 1;
+                    stats.bytes += size;
+                },
+                Err(error) => *stats.errors.entry(error.to_string()).or_insert(0) += 1,
+            }
+            Ok(Loop::Continue(()))
+        }))
+    })
+}
+
+/// The whole body is read so that the connection can be used again
+fn send(request: PreparedRequest) -> Box<Future<Item = (u16, usize), Error = ChromeError>> {
+    match request.pooled() {
+        Ok(request) => Box::new(request.send()
+            .map_err(ChromeError::from)
+            .and_then(|response| {
+                let status = response.status().as_u16();
+                response.body()
+                    .limit(MAX_BODY_SIZE)
+                    .from_err()
+                    .map(move |body| (status, body.len()))
+            })),
+        Err(e) => Box::new(future::err(e)),
+    }
+}
+
+/// The latency below which `p` percent of the requests were answered
+fn percentile(sorted: &[Duration], p: usize) -> Duration {
+    if sorted.is_empty() {
+        return Duration::default();
+    }
+    let rank = (sorted.len() * p + 99) / 100;
+    sorted[rank.max(1) - 1]
+}
+
+fn percentiles(stats: &Stats) -> Vec<(&'static str, Duration)> {
+    let mut sorted = stats.latencies.clone();
+    sorted.sort();
+    vec![
+        ("p50", percentile(&sorted, 50)),
+        ("p90", percentile(&sorted, 90)),
+        ("p99", percentile(&sorted, 99)),
+        ("max", sorted.last().cloned().unwrap_or_default()),
+    ]
+}
+
+/// Counts the latencies in equally wide buckets between the fastest and slowest request
+fn histogram(stats: &Stats) -> Vec<(Duration, usize)> {
+    let (min, max) = match (stats.latencies.iter().min(), stats.latencies.iter().max()) {
+        (Some(&min), Some(&max)) => (millis(min), millis(max)),
+        _ => return Vec::new(),
+    };
+    let width = ((max - min) / HISTOGRAM_BUCKETS as f64).max(0.001);
+    let mut counts = vec![0; HISTOGRAM_BUCKETS];
+    for latency in &stats.latencies {
+        let bucket = ((millis(*latency) - min) / width) as usize;
+        counts[bucket.min(HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+    counts.into_iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let upper = min + width * (i + 1) as f64;
+            (Duration::from_micros((upper * 1000.0) as u64), count)
+        })
+        .collect()
+}
+
+fn throughput(stats: &Stats, elapsed: Duration) -> f64 {
+    stats.latencies.len() as f64 / (millis(elapsed) / 1000.0).max(0.001)
+}
+
+fn print_table(out: &mut Write, stats: &Stats, elapsed: Duration, colored: bool) -> io::Result<()> {
+    let label = |name: &str| {
+        let name = format!("{:<14}", name);
+        if colored { Blue.paint(name).to_string() } else { name }
+    };
+    let responses = stats.latencies.len();
+    let errors: usize = stats.errors.values().sum();
+
+    writeln!(out, "{}{} in {:.2}s, {:.1} requests/s", label("Responses"), responses,
+             millis(elapsed) / 1000.0, throughput(stats, elapsed))?;
+    writeln!(out, "{}{} bytes", label("Transferred"), stats.bytes)?;
+    let latency = percentiles(stats).iter()
+        .map(|&(name, duration)| format!("{} {:.2} ms", name, millis(duration)))
+        .collect::<Vec<_>>()
+        .join("   ");
+    writeln!(out, "{}{}", label("Latency"), latency)?;
+
+    let histogram = histogram(stats);
+    let most = histogram.iter().map(|&(_, count)| count).max().unwrap_or(0).max(1);
+    for (upper, count) in histogram {
+        let bar = "#".repeat((count * HISTOGRAM_WIDTH + most - 1) / most);
+        let bar = if colored { Green.paint(bar).to_string() } else { bar };
+        writeln!(out, "{}{:>10.2} ms  {:>7}  {}", label(""), millis(upper), count, bar)?;
+    }
+
+    let statuses = stats.statuses.iter()
+        .map(|(status, count)| {
+            let text = format!("{}: {}", status, count);
+            if colored && *status >= 400 { Red.paint(text).to_string() } else { text }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(out, "{}{}", label("Status codes"), if statuses.is_empty() { String::from("-") } else { statuses })?;
+
+    writeln!(out, "{}{}", label("Errors"), errors)?;
+    for (error, count) in &stats.errors {
+        let line = format!("{:>7}  {}", count, error);
+        writeln!(out, "{}{}", label(""), if colored { Red.paint(line).to_string() } else { line })?;
+    }
+    Ok(())
+}
+
+fn json_report(stats: &Stats, elapsed: Duration) -> Value {
+    let number = |n: f64| Number::from_f64(n).map(Value::Number).unwrap_or(Value::Null);
+
+    let mut latency = Map::new();
+    for (name, duration) in percentiles(stats) {
+        latency.insert(format!("{}_ms", name), number(millis(duration)));
+    }
+    let histogram = histogram(stats).into_iter()
+        .map(|(upper, count)| {
+            let mut bucket = Map::new();
+            bucket.insert(String::from("upper_ms"), number(millis(upper)));
+            bucket.insert(String::from("count"), Value::from(count));
+            Value::Object(bucket)
+        })
+        .collect();
+    let statuses = stats.statuses.iter()
+        .map(|(status, count)| (status.to_string(), Value::from(*count)))
+        .collect();
+    let errors = stats.errors.iter()
+        .map(|(error, count)| (error.clone(), Value::from(*count)))
+        .collect();
+
+    let mut report = Map::new();
+    report.insert(String::from("responses"), Value::from(stats.latencies.len()));
+    report.insert(String::from("errors"), Value::from(stats.errors.values().sum::<usize>()));
+    report.insert(String::from("elapsed_ms"), number(millis(elapsed)));
+    report.insert(String::from("requests_per_second"), number(throughput(stats, elapsed)));
+    report.insert(String::from("bytes"), Value::from(stats.bytes));
+    report.insert(String::from("latency"), Value::Object(latency));
+    report.insert(String::from("histogram"), Value::Array(histogram));
+    report.insert(String::from("status_codes"), Value::Object(statuses));
+    report.insert(String::from("error_messages"), Value::Object(errors));
+    Value::Object(report)
+}
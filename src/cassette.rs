@@ -0,0 +1,310 @@
+use actix_web::http::{StatusCode, Version};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use base64;
+use bytes::Bytes;
+use futures::future::{self, Future};
+use openssl::sha::sha256;
+use serde_json;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use errors::ChromeError;
+use output::format_version;
+use request::PreparedRequest;
+use response::Response;
+use retry::{Attempt, RetryPolicy};
+use timing::Timing;
+
+/// Whether exchanges are saved to cassettes, or answered from them instead of the network
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// Which parts of a request have to be the same for a cassette to answer it
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchRules {
+    pub method: bool,
+    pub url: bool,
+    pub body: bool,
+    /// Lower case header names
+    pub headers: Vec<String>,
+}
+
+impl Default for MatchRules {
+    fn default() -> Self {
+        MatchRules {
+            method: true,
+            url: true,
+            body: true,
+            headers: Vec::new(),
+        }
+    }
+}
+
+pub fn is_match_on(v: String) -> Result<(), String> {
+    parse_match_on(&v).map(|_| ())
+}
+
+/// Parses `method,url,header:Accept,body` into the parts to match on
+pub fn parse_match_on(v: &str) -> Result<MatchRules, String> {
+    let mut rules = MatchRules { method: false, url: false, body: false, headers: Vec::new() };
+    for part in v.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part {
+            "method" => rules.method = true,
+            "url" => rules.url = true,
+            "body" => rules.body = true,
+            _ if part.starts_with("header:") && part.len() > "header:".len() =>
+                rules.headers.push(part["header:".len()..].to_ascii_lowercase()),
+            _ => return Err(format!("Expected method, url, body or header:NAME, got '{}'", part)),
+        }
+    }
+    Ok(rules)
+}
+
+/// Headers whose values are never written to a cassette, on top of any given with `--redact`
+pub static REDACTED_HEADERS: &[&str] = &["authorization", "proxy-authorization", "cookie"];
+
+/// Stands in for the value of a redacted header
+pub static REDACTED: &str = "REDACTED";
+
+/// A directory of cassettes, each holding one request and the response it got
+#[derive(Clone, Debug)]
+pub struct Cassettes {
+    pub dir: PathBuf,
+    pub mode: CassetteMode,
+    pub rules: MatchRules,
+    /// Lower case names of the request headers whose values are replaced by `REDACTED`
+    pub redact: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Cassette {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body_sha256: String,
+    pub body: RecordedBody,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub version: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// The body after any content encoding was undone
+    pub body: RecordedBody,
+}
+
+/// Text bodies are kept as they are so that cassettes can be read and edited
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordedBody {
+    Text(String),
+    Base64(String),
+}
+
+impl RecordedBody {
+    fn new(bytes: &[u8]) -> RecordedBody {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => RecordedBody::Text(text),
+            Err(_) => RecordedBody::Base64(base64::encode(bytes)),
+        }
+    }
+
+    fn bytes(&self) -> Result<Bytes, String> {
+        match *self {
+            RecordedBody::Text(ref text) => Ok(Bytes::from(text.as_str())),
+            RecordedBody::Base64(ref data) => base64::decode(data)
+                .map(Bytes::from)
+                .map_err(|e| format!("Invalid base64 body: {}", e)),
+        }
+    }
+}
+
+impl RecordedRequest {
+    fn new(request: &PreparedRequest, redact: &[String]) -> RecordedRequest {
+        RecordedRequest {
+            method: String::from(request.method.as_str()),
+            url: request.url.to_string(),
+            headers: header_pairs(&request.headers).into_iter()
+                .map(|(key, value)| {
+                    let value = if redact.contains(&key) { String::from(REDACTED) } else { value };
+                    (key, value)
+                })
+                .collect(),
+            body_sha256: hex_sha256(&request.payload),
+            body: RecordedBody::new(&request.payload),
+        }
+    }
+}
+
+impl Cassettes {
+    /// Sends the request as usual, with the complete response saved to a cassette before it
+    /// is handed on
+    pub fn record(&self, request: PreparedRequest, policy: &RetryPolicy) -> Attempt {
+        let recorded = RecordedRequest::new(&request, &self.redact);
+        let path = self.dir.join(self.file_name(&request));
+        let dir = self.dir.clone();
+        Box::new(request.send(policy).and_then(move |(response, timing)| {
+            let Response { version, status, headers, body } = response;
+            body.and_then(move |bytes| {
+                let cassette = Cassette {
+                    request: recorded,
+                    response: RecordedResponse {
+                        version: String::from(format_version(version)),
+                        status: status.as_u16(),
+                        // The body is stored decoded, so these would no longer describe it
+                        headers: header_pairs(&headers).into_iter()
+                            .filter(|&(ref key, _)| key != "content-encoding" && key != "content-length")
+                            .collect(),
+                        body: RecordedBody::new(&bytes),
+                    },
+                };
+                fs::create_dir_all(&dir)?;
+                serde_json::to_writer_pretty(File::create(&path)?, &cassette)
+                    .map_err(|e| cassette_error(&path, e.to_string()))?;
+                let response = Response {
+                    version: version,
+                    status: status,
+                    headers: headers,
+                    body: Box::new(future::ok(bytes)),
+                };
+                Ok((response, timing))
+            })
+        }))
+    }
+
+    /// Answers the request from the first cassette which matches it, by file name
+    pub fn replay(&self, request: &PreparedRequest) -> Attempt {
+        Box::new(future::result(self.find(request).and_then(|(path, cassette)| {
+            cassette.response.to_response().map_err(|message| cassette_error(&path, message))
+        })).map(|response| (response, Timing::default())))
+    }
+
+    fn find(&self, request: &PreparedRequest) -> Result<(PathBuf, Cassette), ChromeError> {
+        let mut paths = fs::read_dir(&self.dir)
+            .map_err(|e| cassette_error(&self.dir, e.to_string()))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().map(|ext| ext == "json").unwrap_or(false))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            let cassette: Cassette = serde_json::from_reader(File::open(&path)?)
+                .map_err(|e| cassette_error(&path, e.to_string()))?;
+            if self.matches(request, &cassette.request) {
+                return Ok((path, cassette));
+            }
+        }
+        Err(cassette_error(&self.dir, format!("No cassette matches {} {}", request.method, request.url)))
+    }
+
+    fn matches(&self, request: &PreparedRequest, recorded: &RecordedRequest) -> bool {
+        let rules = &self.rules;
+        (!rules.method || request.method.as_str() == recorded.method)
+            && (!rules.url || request.url.as_str() == recorded.url)
+            && (!rules.body || hex_sha256(&request.payload) == recorded.body_sha256)
+            && rules.headers.iter().all(|name| {
+                // A redacted header can only be matched on for being there
+                let redacted = self.redact.contains(name);
+                let sent = request.headers.get_all(name.as_str()).iter()
+                    .map(|value| if redacted {
+                        String::from(REDACTED)
+                    } else {
+                        String::from_utf8_lossy(value.as_bytes()).into_owned()
+                    })
+                    .collect::<Vec<_>>();
+                let recorded = recorded.headers.iter()
+                    .filter(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|&(_, ref value)| value.clone())
+                    .collect::<Vec<_>>();
+                sent == recorded
+            })
+    }
+
+    /// Named after the method and url to be easy to find, with a hash of whatever is matched
+    /// on so that requests which only differ in e.g. their body get cassettes of their own
+    fn file_name(&self, request: &PreparedRequest) -> String {
+        let mut key = Vec::new();
+        if self.rules.method {
+            key.extend_from_slice(request.method.as_str().as_bytes());
+        }
+        key.push(0);
+        if self.rules.url {
+            key.extend_from_slice(request.url.as_str().as_bytes());
+        }
+        key.push(0);
+        if self.rules.body {
+            key.extend_from_slice(&request.payload);
+        }
+        for name in &self.rules.headers {
+            // Secrets stay out of the file name, and can't be told apart when replaying anyway
+            let redacted = self.redact.contains(name);
+            for value in request.headers.get_all(name.as_str()).iter() {
+                key.push(0);
+                key.extend_from_slice(if redacted { REDACTED.as_bytes() } else { value.as_bytes() });
+            }
+        }
+
+        let location = format!("{}{}", request.url.host_str().unwrap_or(""), request.url.path());
+        let mut slug = location.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+            .collect::<String>();
+        slug = slug.trim_matches('-').chars().take(60).collect();
+        format!("{}-{}-{}.json", request.method.as_str().to_lowercase(), slug, &hex_sha256(&key)[..12])
+    }
+}
+
+impl RecordedResponse {
+    fn to_response(&self) -> Result<Response, String> {
+        let status = StatusCode::from_u16(self.status)
+            .map_err(|_| format!("Invalid status {}", self.status))?;
+        let mut headers = HeaderMap::new();
+        for &(ref key, ref value) in &self.headers {
+            let name = HeaderName::from_bytes(key.as_bytes())
+                .map_err(|_| format!("Invalid header name '{}'", key))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| format!("Invalid value for header {}", key))?;
+            headers.append(name, value);
+        }
+        Ok(Response {
+            version: parse_version(&self.version)?,
+            status: status,
+            headers: headers,
+            body: Box::new(future::ok(self.body.bytes()?)),
+        })
+    }
+}
+
+fn parse_version(version: &str) -> Result<Version, String> {
+    match version {
+        "HTTP/0.9" => Ok(Version::HTTP_09),
+        "HTTP/1.0" => Ok(Version::HTTP_10),
+        "HTTP/1.1" => Ok(Version::HTTP_11),
+        "HTTP/2" => Ok(Version::HTTP_2),
+        _ => Err(format!("Unknown HTTP version '{}'", version)),
+    }
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter()
+        .map(|(key, value)| (String::from(key.as_str()), String::from_utf8_lossy(value.as_bytes()).into_owned()))
+        .collect()
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    sha256(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn cassette_error(path: &Path, message: String) -> ChromeError {
+    ChromeError::CassetteError { path: path.display().to_string(), message: message }
+}
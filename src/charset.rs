@@ -0,0 +1,80 @@
+use encoding::{self, DecoderTrap, EncodingRef};
+use encoding::all::UTF_8;
+use encoding::label::encoding_from_whatwg_label;
+
+// Only the start of a document is searched for a `<meta charset>` declaration, the same
+// way browsers do it.
+static META_SNIFF_LEN: usize = 1024;
+
+pub fn is_charset(v: String) -> Result<(), String> {
+    encoding_from_whatwg_label(v.as_str()).map(|_| ())
+        .ok_or(format!("Unknown charset: {}", v))
+}
+
+pub fn get_charset(label: &str) -> Option<EncodingRef> {
+    encoding_from_whatwg_label(label.trim())
+}
+
+/// Decodes `bytes` to a `String`, picking the encoding in the following order:
+///
+///  1. The user supplied override (`--response-charset`)
+///  2. A byte order mark at the start of the body
+///  3. The `charset` parameter of the `Content-Type` header
+///  4. A `<meta charset>` declaration in the first 1024 bytes of the body
+///  5. UTF-8
+///
+/// Undecodable sequences are replaced by U+FFFD rather than failing.
+pub fn decode(bytes: &[u8], content_type_charset: Option<&str>, forced: Option<EncodingRef>) -> String {
+    if let Some(enc) = forced {
+        return enc.decode(bytes, DecoderTrap::Replace).unwrap_or_default();
+    }
+
+    let fallback = content_type_charset
+        .and_then(get_charset)
+        .or_else(|| sniff_meta_charset(bytes))
+        .unwrap_or(UTF_8 as EncodingRef);
+
+    // Takes care of the byte order mark
+    encoding::decode(bytes, DecoderTrap::Replace, fallback).0.unwrap_or_default()
+}
+
+/// Returns `true` if the encoding of `bytes` was declared somewhere (either by a BOM,
+/// the `Content-Type` header or a `<meta>` tag) instead of having to be guessed.
+pub fn is_declared(bytes: &[u8], content_type_charset: Option<&str>) -> bool {
+    has_bom(bytes)
+        || content_type_charset.and_then(get_charset).is_some()
+        || sniff_meta_charset(bytes).is_some()
+}
+
+fn has_bom(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xEF, 0xBB, 0xBF])
+        || bytes.starts_with(&[0xFE, 0xFF])
+        || bytes.starts_with(&[0xFF, 0xFE])
+}
+
+/// Looks for `<meta charset="..">` or `<meta http-equiv="Content-Type" content="..; charset=..">`
+fn sniff_meta_charset(bytes: &[u8]) -> Option<EncodingRef> {
+    let len = if bytes.len() < META_SNIFF_LEN { bytes.len() } else { META_SNIFF_LEN };
+    // Declarations are always ascii so a lossy conversion is fine here
+    let head = String::from_utf8_lossy(&bytes[..len]).to_lowercase();
+
+    let mut rest = head.as_str();
+    while let Some(start) = rest.find("<meta") {
+        rest = &rest[start + 5..];
+        let tag = &rest[..rest.find('>').unwrap_or(rest.len())];
+        if let Some(pos) = tag.find("charset") {
+            let value = tag[pos + 7..]
+                .trim_left()
+                .trim_left_matches('=')
+                .trim_left()
+                .trim_left_matches(|c| c == '"' || c == '\'');
+            let end = value
+                .find(|c: char| c == '"' || c == '\'' || c == ';' || c == '/' || c.is_whitespace())
+                .unwrap_or(value.len());
+            if let Some(enc) = get_charset(&value[..end]) {
+                return Some(enc);
+            }
+        }
+    }
+    None
+}
@@ -1,44 +1,47 @@
 use actix_web::http::Method;
 use atty::{self, Stream};
-use clap::{App as ClapApp, AppSettings, Arg, ArgMatches};
+use clap::{App as ClapApp, AppSettings, Arg, ArgGroup, ArgMatches};
 use console::Term;
-use syntect::highlighting::ThemeSet;
-use syntect::parsing::SyntaxSet;
-use syntect::parsing::syntax_definition::SyntaxDefinition;
 
 #[cfg(windows)]
 use ansi_term;
 
 use std::env;
+use std::process;
+use std::rc::Rc;
 use std::str::FromStr;
 
-use errors::ChromeError;
-use request::BodyType;
-use request_item::{RequestItem, is_request_item, get_request_item};
-
-static NEW_LINES: bool = false;
+use cassette::{CassetteMode, Cassettes, REDACTED_HEADERS, is_match_on, parse_match_on};
+use charset::{get_charset, is_charset};
+use color::{ColorDepth, color_depth};
+use config_file::ConfigFile;
+use connection::HttpVersion;
+use errors::{ChromeError, handle_error};
+use expect::{Expectation, is_expectation};
+use files::expand_path;
+use filter::{Filter, is_filter};
+use format::{FormatOptions, is_format_options};
+use output::{OutputWrap, PagingMode, PrintOptions, Printer};
+use request::{BodyType, RequestOptions, RequestSpec};
+use response::ResponseOptions;
+use retry::{RetryPolicy, is_retry_on, parse_retry_on};
+use schema::Schema;
+use timing::{TimingFormat, is_duration, parse_duration};
+use request_item::{RequestItem, RequestItemType, is_request_item, get_request_item};
 
 pub struct App {
     pub matches: ArgMatches<'static>,
     interactive_output: bool,
+    config_file: ConfigFile,
 }
 
 pub struct Config {
     // Main stuff relating to the request to be made
-    pub items: Vec<RequestItem>,
-    pub method: Method,
-    pub url: String,
+    pub request: RequestSpec,
     // Formatting options, etc.
-    pub colored_output: bool,
-    pub interactive_output: bool,
-    pub term_width: usize,
-    pub true_color: bool,
-    pub verbose: bool,
-    pub body_type: BodyType,
-    pub syntax_set: SyntaxSet,
-    pub theme_set: ThemeSet,
-    // pub output_wrap
-    // pub paging_mode
+    pub printer: Printer,
+    pub request_options: RequestOptions,
+    pub response_options: ResponseOptions,
 }
 
 impl App {
@@ -48,13 +51,31 @@ impl App {
         #[cfg(windows)]
         let interactive_output = interactive_output && ansi_term::enable_ansi_support().is_ok();
 
+        let args: Vec<String> = env::args().collect();
+        let config_file = ConfigFile::load(&args).unwrap_or_else(|error| {
+            handle_error(error);
+            process::exit(1);
+        });
+
+        let mut matches = Self::matches(interactive_output, config_file.args(None, &args));
+
+        // Options for a host can only be known once the url has been parsed
+        let host_args = matches.value_of("URL")
+            .and_then(|url| config_file.host(url))
+            .filter(|host| !host.default_options.is_empty())
+            .map(|host| config_file.args(Some(host), &args));
+        if let Some(host_args) = host_args {
+            matches = Self::matches(interactive_output, host_args);
+        }
+
         App {
-            matches: Self::matches(interactive_output),
+            matches,
             interactive_output,
+            config_file,
         }
     }
 
-    fn matches(interactive_output: bool) -> ArgMatches<'static> {
+    pub fn matches(interactive_output: bool, args: Vec<String>) -> ArgMatches<'static> {
         let clap_color_setting = if interactive_output {
             AppSettings::ColoredHelp
         } else {
@@ -67,6 +88,7 @@ impl App {
             .about("A command-line curl replacement with a better UX")
             .max_term_width(90)
             .global_setting(clap_color_setting)
+            .after_help(include_str!("./help/subcommands.help.txt"))
             .arg(Arg::with_name("METHOD")
                  .help("The HTTP method to be used for the request (GET, POST, PUT, DELETE, ...).")
                  .long_help(include_str!("./help/method.help.txt"))
@@ -89,46 +111,269 @@ impl App {
             .arg(Arg::with_name("verbose")
                  .short("v")
                  .long("verbose")
+                 .multiple(true)
                  .help("Verbose output")
                  .long_help(include_str!("./help/verbose.help.txt"))
             )
-            .arg(Arg::with_name("json")
-                 .short("j")
-                 .long("json")
-                 .help("Force json for request arguments")
+            .args(&body_type_args())
+            .arg(Arg::with_name("raw")
+                 .long("raw")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("BODY")
+                 .help("Send the given string as the body, instead of building one from request items")
+                 .long_help(include_str!("./help/raw.help.txt"))
+            )
+            .arg(Arg::with_name("response-charset")
+                 .long("response-charset")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("CHARSET")
+                 .validator(is_charset)
+                 .help("Override the charset used to decode the response body")
+                 .long_help(include_str!("./help/response-charset.help.txt"))
+            )
+            .arg(Arg::with_name("filter")
+                 .long("filter")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("EXPR")
+                 .validator(is_filter)
+                 .help("Filter the JSON response body using a jq-like expression")
+                 .long_help(include_str!("./help/filter.help.txt"))
+            )
+            .arg(Arg::with_name("format-options")
+                 .long("format-options")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("OPTIONS")
+                 .validator(is_format_options)
+                 .help("Control the formatting of JSON bodies and headers")
+                 .long_help(include_str!("./help/format-options.help.txt"))
+            )
+            .arg(Arg::with_name("pager")
+                 .long("pager")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("WHEN")
+                 .possible_values(&["auto", "always", "never"])
+                 .help("When to page the response through $CHROME_PAGER, $PAGER or less")
+                 .long_help(include_str!("./help/pager.help.txt"))
+            )
+            .arg(Arg::with_name("wrap")
+                 .long("wrap")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("MODE")
+                 .possible_values(&["auto", "never", "character"])
+                 .help("Wrap long lines at the terminal width")
+                 .long_help(include_str!("./help/wrap.help.txt"))
+            )
+            .arg(Arg::with_name("color")
+                 .long("color")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("WHEN")
+                 .possible_values(&["auto", "always", "never", "256", "16"])
+                 .help("When to use colours, and how many")
+                 .long_help(include_str!("./help/color.help.txt"))
+            )
+            .arg(Arg::with_name("timing")
+                 .long("timing")
+                 .takes_value(true)
+                 .multiple(true)
+                 .min_values(0)
+                 .require_equals(true)
+                 .value_name("FORMAT")
+                 .possible_values(&["table", "json"])
+                 .help("Print how long each phase of the request took")
+                 .long_help(include_str!("./help/timing.help.txt"))
+            )
+            .arg(Arg::with_name("retries")
+                 .long("retries")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("N")
+                 .validator(|v| v.parse::<u32>().map(|_| ()).map_err(|_| String::from("Expected a number")))
+                 .help("Retry failed requests up to N times")
+                 .long_help(include_str!("./help/retries.help.txt"))
+            )
+            .arg(Arg::with_name("retry-on")
+                 .long("retry-on")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("CONDITIONS")
+                 .validator(is_retry_on)
+                 .help("Status codes, and 'connect' for connection errors, to retry on [default: 502,503,504,connect]")
+            )
+            .arg(Arg::with_name("retry-max-time")
+                 .long("retry-max-time")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("DURATION")
+                 .validator(is_duration)
+                 .help("Give up retrying once this much time has passed, e.g. 30s")
+            )
+            .arg(Arg::with_name("retry-all")
+                 .long("retry-all")
+                 .multiple(true)
+                 .help("Also retry methods which aren't idempotent, such as POST")
+            )
+            .arg(Arg::with_name("unix-socket")
+                 .long("unix-socket")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("PATH")
+                 .help("Connect through a Unix domain socket instead of the host of the URL")
+                 .long_help(include_str!("./help/unix-socket.help.txt"))
+            )
+            .args(&http_version_args())
+            .arg(Arg::with_name("record")
+                 .long("record")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("DIR")
+                 .conflicts_with("replay")
+                 .help("Save the request and its response as a cassette in DIR")
+                 .long_help(include_str!("./help/cassettes.help.txt"))
+            )
+            .arg(Arg::with_name("replay")
+                 .long("replay")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("DIR")
+                 .help("Answer the request from a matching cassette in DIR instead of the network")
+            )
+            .arg(Arg::with_name("match-on")
+                 .long("match-on")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("PARTS")
+                 .validator(is_match_on)
+                 .requires("cassettes")
+                 .help("What a cassette has to share with the request to be replayed [default: method,url,body]")
+            )
+            .arg(Arg::with_name("redact")
+                 .long("redact")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("HEADER")
+                 .requires("record")
+                 .help("Leave the value of HEADER out of recorded cassettes, like Authorization and Cookie")
+            )
+            .group(ArgGroup::with_name("cassettes")
+                 .args(&["record", "replay"])
+            )
+            .arg(Arg::with_name("expect")
+                 .long("expect")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("ASSERTION")
+                 .validator(is_expectation)
+                 .help("Check the response, e.g. status=201 or 'body.items.length>0'")
+                 .long_help(include_str!("./help/expect.help.txt"))
+            )
+            .arg(Arg::with_name("request-schema")
+                 .long("request-schema")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("FILE")
+                 .help("Check the JSON body against a JSON Schema before sending the request")
+            )
+            .arg(Arg::with_name("response-schema")
+                 .long("response-schema")
+                 .takes_value(true)
+                 .multiple(true)
+                 .number_of_values(1)
+                 .value_name("FILE")
+                 .help("Check the JSON body of the response against a JSON Schema")
+                 .long_help(include_str!("./help/schema.help.txt"))
+            )
+            .arg(Arg::with_name("config")
+                 .long("config")
+                 .takes_value(true)
+                 .value_name("PATH")
+                 .help("Use the given config file instead of the default one")
+                 .long_help(include_str!("./help/config.help.txt"))
             )
-            .arg(Arg::with_name("form")
-                 .short("f")
-                 .long("form")
-                 .help("Force sending as form for request arguments")
-                 .conflicts_with("json")
+            .arg(Arg::with_name("no-config")
+                 .long("no-config")
+                 .conflicts_with("config")
+                 .help("Don't load any config file")
             )
-            .get_matches()
+            .get_matches_from(args)
     }
 
     pub fn config(&self) -> Result<Config, ChromeError> {
         let url = self.matches.value_of("URL").unwrap();
+        let color_depth = color_depth(self.last_value_of("color"), self.interactive_output);
+        let mut request = RequestSpec::new(self.method()?, url)
+            .body_type(body_type(&self.matches))
+            .http_version(http_version(&self.matches))
+            .retry(self.retry_policy())
+            .timed(self.timing().is_some());
+        if let Some(raw) = self.last_value_of("raw") {
+            request = request.raw_body(raw);
+        }
+        // A socket in the url itself wins over --unix-socket
+        if let (None, Some(socket)) = (request.unix_socket.as_ref(), self.last_value_of("unix-socket")) {
+            request = request.unix_socket(socket);
+        }
         let request_items = self.request_items();
-        let body_type = if self.matches.is_present("json") {
-            BodyType::JSON
-        } else if self.matches.is_present("form") {
-            BodyType::Form
-        } else {
-            BodyType::Undecided
-        };
+        let mut items = self.default_headers(&request.url, &request_items);
+        items.extend(request_items);
+        request = request.items(items);
+
+        let term_width = Term::stdout().size().1 as usize;
+        let printer = Printer::new(PrintOptions {
+            colored_output: color_depth != ColorDepth::None,
+            color_depth: color_depth,
+            wrap_width: match self.output_wrap() {
+                OutputWrap::Character => Some(term_width),
+                OutputWrap::None => None,
+            },
+            format_options: self.format_options(),
+        });
 
+        let verbose = self.matches.is_present("verbose");
         Ok(Config {
-            method: self.method()?,
-            url: String::from(url),
-            items: request_items,
-            body_type: body_type,
-            colored_output: self.interactive_output,
-            interactive_output: self.interactive_output,
-            term_width: Term::stdout().size().1 as usize,
-            true_color: is_truecolor_terminal(),
-            verbose: self.matches.is_present("verbose"),
-            syntax_set: get_syntax_set(),
-            theme_set: get_theme_set(),
+            request: request,
+            printer: printer,
+            request_options: RequestOptions {
+                verbose: verbose,
+                cassettes: self.cassettes(),
+                schema: self.schema("request-schema")?,
+            },
+            response_options: ResponseOptions {
+                interactive_output: self.interactive_output,
+                term_width: term_width,
+                term_height: Term::stdout().size().0 as usize,
+                verbose: verbose,
+                response_charset: self.last_value_of("response-charset").and_then(get_charset),
+                // We can unwrap safely here because due to validation, we know this can be parsed
+                filter: self.last_value_of("filter").map(|f| Filter::parse(f).unwrap()),
+                timing: self.timing(),
+                paging_mode: self.paging_mode(),
+                expectations: self.expectations(),
+                schema: self.schema("response-schema")?,
+            },
         })
     }
 
@@ -139,41 +384,192 @@ impl App {
                 values
                     .map(String::from)
                     .map(get_request_item)
-                    .map(Option::unwrap)  // We can unwrap safely here because due to validation, we know this can be parsed
+                    .map(Result::unwrap)  // We can unwrap safely here because due to validation, we know this can be parsed
                     .collect()
             })
             .unwrap_or_else(|| vec![])
     }
 
+    /// Headers from the config file which weren't set on the command line
+    fn default_headers(&self, url: &str, request_items: &[RequestItem]) -> Vec<RequestItem> {
+        let host = self.config_file.host(url);
+        self.config_file.headers(host)
+            .into_iter()
+            .filter(|&(ref name, _)| {
+                !request_items.iter().any(|item| match item.variant {
+                    RequestItemType::HTTPHeader | RequestItemType::EmptyHTTPHeader => item.key.eq_ignore_ascii_case(name),
+                    _ => false,
+                })
+            })
+            .map(|(name, value)| RequestItem {
+                key: name,
+                value: value,
+                variant: RequestItemType::HTTPHeader,
+            })
+            .collect()
+    }
+
+    /// Options may be given both in the config file and on the command line, the last
+    /// one wins.
+    fn last_value_of(&self, name: &str) -> Option<&str> {
+        self.matches.values_of(name).and_then(|values| values.last())
+    }
+
+    fn format_options(&self) -> FormatOptions {
+        let mut options = FormatOptions::default();
+        if let Some(values) = self.matches.values_of("format-options") {
+            for value in values {
+                // Due to validation, we know these can be parsed
+                options.update(value).unwrap();
+            }
+        }
+        options
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        // Due to validation, we know these can be parsed
+        let mut policy = RetryPolicy::default();
+        if let Some(retries) = self.last_value_of("retries") {
+            policy.retries = retries.parse().unwrap();
+        }
+        if let Some(retry_on) = self.last_value_of("retry-on") {
+            let (statuses, on_connect) = parse_retry_on(retry_on).unwrap();
+            policy.on_status = statuses;
+            policy.on_connect = on_connect;
+        }
+        policy.max_time = self.last_value_of("retry-max-time").map(|v| parse_duration(v).unwrap());
+        policy.all_methods = self.matches.is_present("retry-all");
+        policy
+    }
+
+    fn timing(&self) -> Option<TimingFormat> {
+        if !self.matches.is_present("timing") {
+            return None;
+        }
+        match self.last_value_of("timing") {
+            Some("json") => Some(TimingFormat::Json),
+            _ => Some(TimingFormat::Table),
+        }
+    }
+
+    fn output_wrap(&self) -> OutputWrap {
+        match self.last_value_of("wrap") {
+            Some("character") => OutputWrap::Character,
+            Some("never") => OutputWrap::None,
+            _ => if self.interactive_output { OutputWrap::Character } else { OutputWrap::None },
+        }
+    }
+
+    fn cassettes(&self) -> Option<Cassettes> {
+        let (dir, mode) = if let Some(dir) = self.last_value_of("record") {
+            (dir, CassetteMode::Record)
+        } else if let Some(dir) = self.last_value_of("replay") {
+            (dir, CassetteMode::Replay)
+        } else {
+            return None;
+        };
+        Some(Cassettes {
+            dir: expand_path(dir),
+            mode: mode,
+            // Due to validation, we know this can be parsed
+            rules: self.last_value_of("match-on").map(|v| parse_match_on(v).unwrap()).unwrap_or_default(),
+            redact: REDACTED_HEADERS.iter()
+                .map(|name| String::from(*name))
+                .chain(self.matches.values_of("redact").into_iter().flat_map(|v| v).map(str::to_ascii_lowercase))
+                .collect(),
+        })
+    }
+
+    /// Every `--expect` counts, unlike the options where the last one wins
+    fn expectations(&self) -> Vec<Expectation> {
+        self.matches
+            .values_of("expect")
+            // Due to validation, we know these can be parsed
+            .map(|values| values.map(|v| Expectation::parse(v).unwrap()).collect())
+            .unwrap_or_default()
+    }
+
+    fn schema(&self, name: &str) -> Result<Option<Rc<Schema>>, ChromeError> {
+        match self.last_value_of(name) {
+            Some(path) => Ok(Some(Rc::new(Schema::load(path)?))),
+            None => Ok(None),
+        }
+    }
+
+    fn paging_mode(&self) -> PagingMode {
+        if !self.interactive_output {
+            return PagingMode::Never;
+        }
+        match self.last_value_of("pager") {
+            Some("always") => PagingMode::Always,
+            Some("never") => PagingMode::Never,
+            _ => PagingMode::QuietIfFits,
+        }
+    }
+
     fn method(&self) -> Result<Method, ChromeError> {
         let method = self.matches.value_of("METHOD").unwrap().to_uppercase();
         Method::from_str(method.as_str()).map_err(ChromeError::from)
     }
 }
 
-fn is_truecolor_terminal() -> bool {
-    env::var("COLORTERM")
-        .map(|colorterm| colorterm == "truecolor" || colorterm == "24bit")
-        .unwrap_or(false)
+/// `--json` and `--form`, which `chrome bench` takes as well
+pub fn body_type_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("json")
+            .short("j")
+            .long("json")
+            .multiple(true)
+            .conflicts_with("form")
+            .help("Force json for request arguments"),
+        Arg::with_name("form")
+            .short("f")
+            .long("form")
+            .multiple(true)
+            .conflicts_with("json")
+            .help("Force sending as form for request arguments"),
+    ]
 }
 
-pub fn get_syntax_set() -> SyntaxSet {
-    let http_def: SyntaxDefinition = SyntaxDefinition::load_from_str(
-        include_str!("./http.sublime-syntax"),
-        NEW_LINES,
-        Some("HTTP")
-    ).expect("Unable to parse http sublime syntax");
-
-    let mut ss = if NEW_LINES {
-        SyntaxSet::load_defaults_newlines()
+pub fn body_type(matches: &ArgMatches) -> BodyType {
+    if matches.is_present("json") {
+        BodyType::JSON
+    } else if matches.is_present("form") {
+        BodyType::Form
     } else {
-        SyntaxSet::load_defaults_nonewlines()
-    };
-    ss.add_syntax(http_def);
-    ss.link_syntaxes();
-    ss
+        BodyType::Undecided
+    }
+}
+
+/// `--http1.1`, `--http2` and `--http2-prior-knowledge`, which `chrome bench` knows about in
+/// order to turn down HTTP/2
+pub fn http_version_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("http1.1")
+            .long("http1.1")
+            .multiple(true)
+            .overrides_with_all(&["http2", "http2-prior-knowledge"])
+            .help("Use HTTP/1.1 (the default)"),
+        Arg::with_name("http2")
+            .long("http2")
+            .multiple(true)
+            .overrides_with_all(&["http1.1", "http2-prior-knowledge"])
+            .help("Use HTTP/2 if the server agrees to it during the TLS handshake")
+            .long_help(include_str!("./help/http2.help.txt")),
+        Arg::with_name("http2-prior-knowledge")
+            .long("http2-prior-knowledge")
+            .multiple(true)
+            .overrides_with_all(&["http1.1", "http2"])
+            .help("Use HTTP/2 without negotiating it first, also for http:// URLs"),
+    ]
 }
 
-pub fn get_theme_set() -> ThemeSet {
-    ThemeSet::load_defaults()
+pub fn http_version(matches: &ArgMatches) -> HttpVersion {
+    if matches.is_present("http2-prior-knowledge") {
+        HttpVersion::Http2PriorKnowledge
+    } else if matches.is_present("http2") {
+        HttpVersion::Http2
+    } else {
+        HttpVersion::Http11
+    }
 }
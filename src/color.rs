@@ -0,0 +1,189 @@
+use syntect::highlighting::{Color, Style};
+use syntect::util::as_24_bit_terminal_escaped;
+
+use std::env;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorDepth {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    None,
+}
+
+/// The 16 standard colours, as rendered by xterm
+static ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (205, 0, 0), (0, 205, 0), (205, 205, 0),
+    (0, 0, 238), (205, 0, 205), (0, 205, 205), (229, 229, 229),
+    (127, 127, 127), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (92, 92, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+/// The levels of each channel in the 6x6x6 colour cube of 256 colour terminals
+static CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// Works out the colour depth to use from `--color` (if given) and the environment:
+///
+///  - `NO_COLOR` disables colours
+///  - `FORCE_COLOR` enables them even if stdout isn't a terminal, with `1`, `2` and `3`
+///    selecting 16 colours, 256 colours and true colour respectively (`0` disables them)
+///  - otherwise `COLORTERM` and `TERM` decide the depth
+pub fn color_depth(color: Option<&str>, interactive_output: bool) -> ColorDepth {
+    depth_from(color, interactive_output, &|name| env::var(name).ok())
+}
+
+/// `color_depth`, reading the environment through `var`
+fn depth_from(color: Option<&str>, interactive_output: bool, var: &Fn(&str) -> Option<String>) -> ColorDepth {
+    match color {
+        Some("never") => return ColorDepth::None,
+        Some("always") => return detect_depth(var),
+        Some("256") => return ColorDepth::Ansi256,
+        Some("16") => return ColorDepth::Ansi16,
+        _ => (),
+    }
+
+    if var("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false) {
+        return ColorDepth::None;
+    }
+    if let Some(force) = var("FORCE_COLOR") {
+        return match force.as_str() {
+            "0" | "false" => ColorDepth::None,
+            "1" => ColorDepth::Ansi16,
+            "2" => ColorDepth::Ansi256,
+            "3" => ColorDepth::TrueColor,
+            _ => detect_depth(var),
+        };
+    }
+    if !interactive_output || var("TERM").map(|term| term == "dumb").unwrap_or(false) {
+        return ColorDepth::None;
+    }
+    detect_depth(var)
+}
+
+fn detect_depth(var: &Fn(&str) -> Option<String>) -> ColorDepth {
+    let colorterm = var("COLORTERM").unwrap_or_default();
+    let term = var("TERM").unwrap_or_default();
+    if colorterm == "truecolor" || colorterm == "24bit" || term.contains("truecolor") || term.contains("direct") {
+        ColorDepth::TrueColor
+    } else if term.contains("256color") {
+        ColorDepth::Ansi256
+    } else {
+        ColorDepth::Ansi16
+    }
+}
+
+/// Like `as_24_bit_terminal_escaped`, but falls back to the nearest palette entry on
+/// terminals which don't support true colour.
+pub fn as_terminal_escaped(ranges: &[(Style, &str)], depth: ColorDepth) -> String {
+    if depth == ColorDepth::TrueColor {
+        // Also sets the background, like before the fallbacks existed
+        return as_24_bit_terminal_escaped(ranges, true);
+    }
+
+    let mut escaped = String::new();
+    for &(ref style, text) in ranges {
+        match depth {
+            ColorDepth::Ansi256 => {
+                escaped.push_str(&format!("\x1b[38;5;{}m", nearest_256(style.foreground)));
+            },
+            ColorDepth::Ansi16 => {
+                let index = nearest_16(style.foreground);
+                let code = if index < 8 { 30 + index } else { 90 + index - 8 };
+                escaped.push_str(&format!("\x1b[{}m", code));
+            },
+            ColorDepth::TrueColor | ColorDepth::None => (),
+        }
+        escaped.push_str(text);
+    }
+    escaped
+}
+
+fn distance(color: Color, (r, g, b): (u8, u8, u8)) -> u32 {
+    let dr = color.r as i32 - r as i32;
+    let dg = color.g as i32 - g as i32;
+    let db = color.b as i32 - b as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+fn nearest_16(color: Color) -> usize {
+    (0..ANSI_16.len())
+        .min_by_key(|&i| distance(color, ANSI_16[i]))
+        .unwrap_or(7)
+}
+
+fn nearest_256(color: Color) -> usize {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| (c as i32 - CUBE_LEVELS[i] as i32).abs())
+            .unwrap_or(0)
+    };
+    let (r, g, b) = (level(color.r), level(color.g), level(color.b));
+    let cube_index = 16 + 36 * r + 6 * g + b;
+    let cube_color = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+
+    // The grayscale ramp goes from 8 to 238 in steps of 10
+    let average = (color.r as u32 + color.g as u32 + color.b as u32) / 3;
+    let gray = if average < 8 { 0 } else if average > 238 { 23 } else { ((average - 8 + 5) / 10) as usize };
+    let gray_level = (8 + 10 * gray) as u8;
+    let gray_color = (gray_level, gray_level, gray_level);
+
+    if distance(color, gray_color) < distance(color, cube_color) {
+        232 + gray
+    } else {
+        cube_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn rgb(r: u8, g: u8, b: u8) -> Color {
+        Color { r: r, g: g, b: b, a: 0xff }
+    }
+
+    /// Works out the colour depth as if only `vars` were set
+    fn depth_with_env(vars: &[(&str, &str)], color: Option<&str>, interactive_output: bool) -> ColorDepth {
+        let vars: HashMap<&str, &str> = vars.iter().cloned().collect();
+        depth_from(color, interactive_output, &|name| vars.get(name).map(|value| String::from(*value)))
+    }
+
+    #[test]
+    fn nearest_16_picks_the_closest_xterm_colour() {
+        assert_eq!(nearest_16(rgb(0, 0, 0)), 0);
+        assert_eq!(nearest_16(rgb(200, 10, 0)), 1);
+        assert_eq!(nearest_16(rgb(255, 0, 0)), 9);
+        assert_eq!(nearest_16(rgb(130, 120, 130)), 8);
+        assert_eq!(nearest_16(rgb(250, 250, 250)), 15);
+    }
+
+    #[test]
+    fn nearest_256_picks_from_the_cube_or_the_grayscale_ramp() {
+        assert_eq!(nearest_256(rgb(0, 0, 0)), 16);
+        assert_eq!(nearest_256(rgb(255, 0, 0)), 196);
+        assert_eq!(nearest_256(rgb(95, 135, 175)), 67);
+        assert_eq!(nearest_256(rgb(255, 255, 255)), 231);
+        assert_eq!(nearest_256(rgb(128, 128, 128)), 244);
+        assert_eq!(nearest_256(rgb(8, 8, 8)), 232);
+    }
+
+    #[test]
+    fn color_depth_follows_the_flag_then_the_environment() {
+        assert_eq!(depth_with_env(&[("NO_COLOR", "1")], Some("256"), true), ColorDepth::Ansi256);
+        assert_eq!(depth_with_env(&[("TERM", "xterm-256color")], Some("never"), true), ColorDepth::None);
+
+        assert_eq!(depth_with_env(&[("NO_COLOR", "1"), ("FORCE_COLOR", "3")], None, true), ColorDepth::None);
+        assert_eq!(depth_with_env(&[("NO_COLOR", ""), ("TERM", "xterm")], None, true), ColorDepth::Ansi16);
+        assert_eq!(depth_with_env(&[("FORCE_COLOR", "2")], None, false), ColorDepth::Ansi256);
+        assert_eq!(depth_with_env(&[("FORCE_COLOR", "0"), ("COLORTERM", "truecolor")], None, true),
+                   ColorDepth::None);
+
+        assert_eq!(depth_with_env(&[("TERM", "xterm-256color")], None, false), ColorDepth::None);
+        assert_eq!(depth_with_env(&[("TERM", "dumb"), ("COLORTERM", "truecolor")], None, true), ColorDepth::None);
+        assert_eq!(depth_with_env(&[("TERM", "xterm-256color")], None, true), ColorDepth::Ansi256);
+        assert_eq!(depth_with_env(&[("TERM", "xterm-256color"), ("COLORTERM", "24bit")], None, true),
+                   ColorDepth::TrueColor);
+        assert_eq!(depth_with_env(&[("TERM", "xterm")], None, true), ColorDepth::Ansi16);
+    }
+}
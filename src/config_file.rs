@@ -0,0 +1,185 @@
+use serde_json;
+use url::Url;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::path::PathBuf;
+
+use errors::ChromeError;
+
+/// The contents of `config.json`:
+///
+/// ```json
+/// {
+///   "default_options": ["--format-options=json.indent:4"],
+///   "headers": { "Accept-Language": "en" },
+///   "hosts": {
+///     "api.example.com": {
+///       "default_options": ["--json"],
+///       "headers": { "Authorization": "Bearer ..." }
+///     }
+///   }
+/// }
+/// ```
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub default_options: Vec<String>,
+    pub headers: BTreeMap<String, String>,
+    pub hosts: BTreeMap<String, HostConfig>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HostConfig {
+    pub default_options: Vec<String>,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl ConfigFile {
+    /// Loads the config file given by `--config`, or the default one if it exists.
+    /// `--no-config` skips loading altogether.
+    pub fn load(args: &[String]) -> Result<ConfigFile, ChromeError> {
+        let mut explicit_path = None;
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            if arg == "--" {
+                break;
+            } else if arg == "--no-config" {
+                return Ok(ConfigFile::default());
+            } else if arg == "--config" {
+                explicit_path = iter.next().map(PathBuf::from);
+            } else if arg.starts_with("--config=") {
+                explicit_path = Some(PathBuf::from(&arg["--config=".len()..]));
+            }
+        }
+
+        match explicit_path {
+            Some(path) => ConfigFile::from_path(path),
+            None => match default_path() {
+                Some(ref path) if path.is_file() => ConfigFile::from_path(path.clone()),
+                _ => Ok(ConfigFile::default()),
+            },
+        }
+    }
+
+    fn from_path(path: PathBuf) -> Result<ConfigFile, ChromeError> {
+        let file = File::open(&path).map_err(|error| ChromeError::ConfigError {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })?;
+        serde_json::from_reader(file).map_err(|error| ChromeError::ConfigError {
+            path: path.display().to_string(),
+            message: error.to_string(),
+        })
+    }
+
+    /// Finds the section for the host of `url`, either by `host:port` or just by `host`.
+    pub fn host(&self, url: &str) -> Option<&HostConfig> {
+        let parsed = Url::parse(url)
+            .or_else(|_| Url::parse(&format!("http://{}", url)))
+            .ok()?;
+        let host = parsed.host_str()?;
+        parsed.port()
+            .and_then(|port| self.hosts.get(&format!("{}:{}", host, port)))
+            .or_else(|| self.hosts.get(host))
+    }
+
+    /// Builds the argument list clap should parse: the program name, followed by the default
+    /// options (global ones first, then those for the host) and finally the actual arguments.
+    ///
+    /// `--json` and `--form` conflict with each other, so a default one is left out when the
+    /// host options or the command line choose a body type of their own.
+    pub fn args(&self, host: Option<&HostConfig>, args: &[String]) -> Vec<String> {
+        let host_options = host.map(|host| &host.default_options[..]).unwrap_or(&[]);
+        let given = &args[args.len().min(1)..];
+        let has_body_type = |options: &[String]| options.iter().any(|option| is_body_type(option));
+
+        let mut full_args: Vec<String> = args.iter().take(1).cloned().collect();
+        let global_options = self.default_options.iter()
+            .filter(|option| !is_body_type(option) || !(has_body_type(host_options) || has_body_type(given)));
+        full_args.extend(global_options.cloned());
+        full_args.extend(host_options.iter()
+            .filter(|option| !is_body_type(option) || !has_body_type(given))
+            .cloned());
+        full_args.extend(given.iter().cloned());
+        full_args
+    }
+
+    /// The default headers, with the host specific ones taking precedence.
+    pub fn headers(&self, host: Option<&HostConfig>) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = self.headers.iter()
+            .filter(|&(name, _)| {
+                host.map(|h| !h.headers.keys().any(|n| n.eq_ignore_ascii_case(name))).unwrap_or(true)
+            })
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        if let Some(host) = host {
+            headers.extend(host.headers.iter().map(|(name, value)| (name.clone(), value.clone())));
+        }
+        headers
+    }
+}
+
+fn is_body_type(option: &str) -> bool {
+    ["--json", "-j", "--form", "-f"].contains(&option)
+}
+
+fn default_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join(crate_name!()).join("config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cli::App;
+
+    fn config(json: &str) -> ConfigFile {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn args(config: &ConfigFile, host: Option<&str>, args: &[&str]) -> Vec<String> {
+        let args = args.iter().map(|arg| String::from(*arg)).collect::<Vec<_>>();
+        config.args(host.and_then(|host| config.host(host)), &args)
+    }
+
+    #[test]
+    fn default_options_come_before_the_arguments() {
+        let config = config(r#"{"default_options": ["-v"], "hosts": {"example.org": {"default_options": ["--json"]}}}"#);
+        assert_eq!(args(&config, Some("example.org"), &["chrome", "get", "example.org"]),
+                   vec!["chrome", "-v", "--json", "get", "example.org"]);
+    }
+
+    #[test]
+    fn a_body_type_replaces_the_default_one() {
+        let config = config(r#"{"default_options": ["--form"], "hosts": {"example.org": {"default_options": ["-j"]}}}"#);
+        assert_eq!(args(&config, Some("example.org"), &["chrome", "get", "example.org"]),
+                   vec!["chrome", "-j", "get", "example.org"]);
+        assert_eq!(args(&config, Some("example.org"), &["chrome", "--form", "get", "example.org"]),
+                   vec!["chrome", "--form", "get", "example.org"]);
+        assert_eq!(args(&config, None, &["chrome", "-j", "get", "example.org"]),
+                   vec!["chrome", "-j", "get", "example.org"]);
+    }
+
+    #[test]
+    fn unreadable_files_name_the_path() {
+        let error = ConfigFile::from_path(PathBuf::from("/nonexistent/chrome.json")).err().unwrap();
+        assert!(error.to_string().starts_with("Config file error in /nonexistent/chrome.json: "), "{}", error);
+    }
+
+    #[test]
+    fn options_can_be_repeated_on_the_command_line() {
+        let config = config(r#"{"default_options": ["--timing", "--wrap=never"]}"#);
+        let matches = App::matches(false, args(&config, None, &["chrome", "--timing=json", "get", "example.org"]));
+        assert_eq!(matches.values_of("timing").and_then(|values| values.last()), Some("json"));
+        assert_eq!(matches.value_of("URL"), Some("example.org"));
+    }
+}
@@ -0,0 +1,181 @@
+use actix_web::actix::SystemService;
+use actix_web::actix::actors::resolver::{Resolve, Resolver};
+use futures::future::{self, Future};
+use openssl::error::ErrorStack;
+use openssl::ssl::{SslConnector, SslMethod};
+use tokio_openssl::{SslConnectorExt, SslStream};
+use tokio_tcp::TcpStream;
+use tokio_timer::Timeout;
+#[cfg(unix)]
+use tokio_uds::UnixStream;
+use url::Url;
+use url::percent_encoding::percent_decode;
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use errors::ChromeError;
+
+/// How long it took to set up a connection
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ConnectTiming {
+    pub dns: Duration,
+    pub connect: Duration,
+    pub tls: Option<Duration>,
+}
+
+/// Which protocol version to speak, as selected with `--http1.1`, `--http2` and
+/// `--http2-prior-knowledge`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HttpVersion {
+    Http11,
+    /// HTTP/2 if the server agrees to it through ALPN, which needs TLS
+    Http2,
+    /// HTTP/2 without negotiating it first, also over plain TCP (h2c)
+    Http2PriorKnowledge,
+}
+
+/// A connection before any HTTP has been spoken over it
+pub enum Stream {
+    Tcp(TcpStream),
+    Tls(SslStream<TcpStream>),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    /// Whether the server picked `h2` during the TLS handshake
+    pub fn negotiated_h2(&self) -> bool {
+        match *self {
+            Stream::Tls(ref stream) => stream.get_ref().ssl().selected_alpn_protocol() == Some(&b"h2"[..]),
+            _ => false,
+        }
+    }
+}
+
+static UNIX_SCHEME: &'static str = "http+unix://";
+
+/// How long to wait for each address of a host to accept the connection
+static CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Splits a `http+unix://%2Fvar%2Frun%2Fdocker.sock/containers/json` url into the path of
+/// the socket and a plain http url for the request itself.
+pub fn split_unix_url(url: &str) -> Option<(PathBuf, String)> {
+    if !url.starts_with(UNIX_SCHEME) {
+        return None;
+    }
+    let rest = &url[UNIX_SCHEME.len()..];
+    let split = rest.find(|c: char| c == '/' || c == '?').unwrap_or(rest.len());
+    let (socket, path) = rest.split_at(split);
+    let socket = percent_decode(socket.as_bytes()).decode_utf8_lossy().into_owned();
+    Some((PathBuf::from(socket), format!("http://localhost{}", path)))
+}
+
+/// Opens a connection to the host of `url` (or to `unix_socket` if given) ourselves instead
+/// of leaving it to the actix connector, so that every phase of setting it up can be timed
+/// and the protocol version can be negotiated.
+pub fn connect(url: &Url, unix_socket: Option<&Path>, version: HttpVersion) -> Box<Future<Item = (Stream, ConnectTiming), Error = ChromeError>> {
+    if let Some(path) = unix_socket {
+        return connect_unix(path);
+    }
+
+    let (host, port) = match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => (String::from(host), port),
+        _ => return Box::new(future::err(ChromeError::ConnectionError {
+            message: format!("No host in url {}", url),
+        })),
+    };
+    let https = url.scheme() == "https";
+
+    // actix's resolver doesn't block the event loop, unlike `to_socket_addrs`
+    let dns_start = Instant::now();
+    let resolve_host = host.clone();
+    let tcp = Resolver::from_registry()
+        .send(Resolve::host_and_port(host.clone(), port))
+        .then(move |resolved| match resolved {
+            Ok(Ok(addrs)) => Ok(addrs),
+            Ok(Err(e)) => Err(ChromeError::ConnectionError { message: format!("Could not resolve {}: {}", resolve_host, e) }),
+            Err(e) => Err(ChromeError::ConnectionError { message: e.to_string() }),
+        })
+        .and_then(move |addrs| {
+            let dns = dns_start.elapsed();
+            let connect_start = Instant::now();
+            connect_any(addrs, None).map(move |stream| (stream, dns, connect_start.elapsed()))
+        });
+
+    if !https {
+        return Box::new(tcp.map(move |(stream, dns, connect)| {
+            (Stream::Tcp(stream), ConnectTiming { dns: dns, connect: connect, tls: None })
+        }));
+    }
+
+    Box::new(tcp.and_then(move |(stream, dns, connect)| {
+        let connector = match ssl_connector(version) {
+            Ok(connector) => connector,
+            Err(e) => return future::Either::A(future::err(ChromeError::ConnectionError {
+                message: e.to_string(),
+            })),
+        };
+        let tls_start = Instant::now();
+        future::Either::B(connector.connect_async(&host, stream)
+            .map_err(|e| ChromeError::ConnectionError { message: e.to_string() })
+            .map(move |stream| {
+                let timing = ConnectTiming { dns: dns, connect: connect, tls: Some(tls_start.elapsed()) };
+                (Stream::Tls(stream), timing)
+            }))
+    }))
+}
+
+/// Tries each address in the order they were resolved in, e.g. IPv6 before IPv4, giving each
+/// `CONNECT_TIMEOUT`. Fails with the error of the last one.
+fn connect_any(mut addrs: VecDeque<SocketAddr>, last_error: Option<ChromeError>) -> Box<Future<Item = TcpStream, Error = ChromeError>> {
+    let addr = match addrs.pop_front() {
+        Some(addr) => addr,
+        None => return Box::new(future::err(last_error.unwrap_or(ChromeError::ConnectionError {
+            message: String::from("The host has no addresses"),
+        }))),
+    };
+    Box::new(Timeout::new(TcpStream::connect(&addr), CONNECT_TIMEOUT)
+        .then(move |connected| -> Box<Future<Item = TcpStream, Error = ChromeError>> {
+            let error = match connected {
+                Ok(stream) => return Box::new(future::ok(stream)),
+                Err(ref e) if e.is_elapsed() => format!("Timed out connecting to {} after {}s", addr, CONNECT_TIMEOUT.as_secs()),
+                Err(e) => match e.into_inner() {
+                    Some(e) => format!("Could not connect to {}: {}", addr, e),
+                    None => format!("Could not connect to {}", addr),
+                },
+            };
+            connect_any(addrs, Some(ChromeError::ConnectionError { message: error }))
+        }))
+}
+
+/// Offers the protocols we're willing to speak through ALPN, in order of preference
+fn ssl_connector(version: HttpVersion) -> Result<SslConnector, ErrorStack> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    match version {
+        HttpVersion::Http11 => builder.set_alpn_protos(b"\x08http/1.1")?,
+        HttpVersion::Http2 => builder.set_alpn_protos(b"\x02h2\x08http/1.1")?,
+        HttpVersion::Http2PriorKnowledge => builder.set_alpn_protos(b"\x02h2")?,
+    }
+    Ok(builder.build())
+}
+
+#[cfg(unix)]
+fn connect_unix(path: &Path) -> Box<Future<Item = (Stream, ConnectTiming), Error = ChromeError>> {
+    let connect_start = Instant::now();
+    Box::new(UnixStream::connect(path)
+        .map_err(ChromeError::from)
+        .map(move |stream| {
+            let timing = ConnectTiming { dns: Duration::default(), connect: connect_start.elapsed(), tls: None };
+            (Stream::Unix(stream), timing)
+        }))
+}
+
+#[cfg(not(unix))]
+fn connect_unix(_path: &Path) -> Box<Future<Item = (Stream, ConnectTiming), Error = ChromeError>> {
+    Box::new(future::err(ChromeError::ConnectionError {
+        message: String::from("Unix domain sockets are not supported on this platform"),
+    }))
+}
@@ -1,6 +1,9 @@
 use actix_web;
 use actix_web::client;
 use actix_web::error;
+use h2;
+use http;
+use http::header::{InvalidHeaderName, InvalidHeaderValue};
 use http::method;
 use serde_json;
 use serde_urlencoded;
@@ -12,12 +15,32 @@ use std::io;
 pub enum ChromeError {
     #[fail(display = "ActixWebError: {}", error)]
     ActixWebError { error: actix_web::Error },
+    #[fail(display = "Body error: {}", message)]
+    BodyError { message: String },
     #[fail(display = "Payload error: {}", error)]
     BodyParseError { error: error::PayloadError },
+    #[fail(display = "Cassette error in {}: {}", path, message)]
+    CassetteError { path: String, message: String },
+    #[fail(display = "Connection error: {}", message)]
+    ConnectionError { message: String },
+    #[fail(display = "Config file error in {}: {}", path, message)]
+    ConfigError { path: String, message: String },
+    #[fail(display = "{} of {} expectations failed", failed, total)]
+    ExpectationFailed { failed: usize, total: usize },
+    #[fail(display = "Filter error: {}", message)]
+    FilterError { message: String },
+    #[fail(display = "HTTP/2 error: {}", error)]
+    Http2Error { error: h2::Error },
+    #[fail(display = "Header Parse error: {}", error)]
+    HeaderParseError { error: http::Error },
     #[fail(display = "IO error: {}", error)]
     IoError { error: io::Error },
     #[fail(display = "Method Parse error: {}", error)]
     MethodParseError { error: method::InvalidMethod },
+    #[fail(display = "Schema error in {}: {}", path, message)]
+    SchemaError { path: String, message: String },
+    #[fail(display = "The {} doesn't match the schema in {}", what, path)]
+    SchemaMismatch { what: String, path: String },
     #[fail(display = "SendRequestFailed: {}", error)]
     SendRequestFailed { error: client::SendRequestError },
     #[fail(display = "Uri Parse error: {}", error)]
@@ -26,6 +49,17 @@ pub enum ChromeError {
     UnexpectedError,
 }
 
+impl ChromeError {
+    /// Failed expectations and schemas get their own exit code, so that CI can tell them
+    /// apart from requests which couldn't be made
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            ChromeError::ExpectationFailed { .. } | ChromeError::SchemaMismatch { .. } => 4,
+            _ => 1,
+        }
+    }
+}
+
 impl From<actix_web::Error> for ChromeError {
     fn from(error: actix_web::Error) -> ChromeError {
         ChromeError::ActixWebError { error: error }
@@ -38,6 +72,24 @@ impl From<client::SendRequestError> for ChromeError {
     }
 }
 
+impl From<h2::Error> for ChromeError {
+    fn from(error: h2::Error) -> ChromeError {
+        ChromeError::Http2Error { error: error }
+    }
+}
+
+impl From<InvalidHeaderName> for ChromeError {
+    fn from(error: InvalidHeaderName) -> ChromeError {
+        ChromeError::HeaderParseError { error: http::Error::from(error) }
+    }
+}
+
+impl From<InvalidHeaderValue> for ChromeError {
+    fn from(error: InvalidHeaderValue) -> ChromeError {
+        ChromeError::HeaderParseError { error: http::Error::from(error) }
+    }
+}
+
 impl From<io::Error> for ChromeError {
     fn from(error: io::Error) -> ChromeError {
         ChromeError::IoError { error: error }
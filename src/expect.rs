@@ -0,0 +1,393 @@
+use actix_web::http::StatusCode;
+use actix_web::http::header::HeaderMap;
+use ansi_term::Colour::{self, Green, Red};
+use serde_json::{self, Value};
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+use timing::{millis, parse_duration};
+
+/// A check on the response given with `--expect`, e.g. `status=201` or `body.items.length>0`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expectation {
+    /// As it was given, for the report
+    pub source: String,
+    pub subject: Subject,
+    pub op: Op,
+    pub expected: String,
+}
+
+/// The part of the response an expectation is about
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subject {
+    Status,
+    /// A lower case header name
+    Header(String),
+    /// The keys and indices leading into a JSON body, or nothing for the whole body
+    Body(Vec<String>),
+    Time,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Contains,
+    NotContains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Exists,
+    NotExists,
+}
+
+/// Longer operators first, so that `<=` isn't taken for `<`
+static OPERATORS: &[(&str, Op)] = &[
+    ("!=", Op::NotEq),
+    ("!~", Op::NotContains),
+    ("<=", Op::Le),
+    (">=", Op::Ge),
+    ("=", Op::Eq),
+    ("~", Op::Contains),
+    ("<", Op::Lt),
+    (">", Op::Gt),
+];
+
+/// What the expectations are checked against
+pub struct Actual<'a> {
+    pub status: StatusCode,
+    pub headers: &'a HeaderMap,
+    /// The body if it is JSON
+    pub json: Option<&'a Value>,
+    pub text: &'a str,
+    pub time: Duration,
+}
+
+/// An expectation which wasn't met, with both sides described for the report
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub source: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+pub fn is_expectation(v: String) -> Result<(), String> {
+    Expectation::parse(&v).map(|_| ())
+}
+
+impl Expectation {
+    pub fn parse(source: &str) -> Result<Expectation, String> {
+        let source = source.trim();
+        for &(suffix, op) in &[(" !exists", Op::NotExists), (" exists", Op::Exists)] {
+            if source.ends_with(suffix) {
+                return Ok(Expectation {
+                    source: String::from(source),
+                    subject: parse_subject(source[..source.len() - suffix.len()].trim())?,
+                    op: op,
+                    expected: String::new(),
+                });
+            }
+        }
+
+        let start = source.find(|c: char| "=!~<>".contains(c))
+            .ok_or_else(|| format!("Expected an operator such as =, ~, < or exists in '{}'", source))?;
+        let rest = &source[start..];
+        let &(text, op) = OPERATORS.iter()
+            .find(|&&(text, _)| rest.starts_with(text))
+            .ok_or_else(|| format!("Expected =, !=, ~, !~, <, <=, > or >= in '{}'", source))?;
+        let subject = parse_subject(source[..start].trim())?;
+        let expected = rest[text.len()..].trim();
+
+        let numeric = [Op::Lt, Op::Le, Op::Gt, Op::Ge].contains(&op);
+        if subject == Subject::Time {
+            parse_duration(expected)?;
+            if op == Op::Contains || op == Op::NotContains {
+                return Err(String::from("time can only be compared, e.g. time<500ms"));
+            }
+        } else if numeric && expected.parse::<f64>().is_err() {
+            return Err(format!("Expected a number after {}, got '{}'", text, expected));
+        }
+
+        Ok(Expectation {
+            source: String::from(source),
+            subject: subject,
+            op: op,
+            expected: String::from(expected),
+        })
+    }
+
+    pub fn check(&self, actual: &Actual) -> Option<Failure> {
+        let value = self.resolve(actual);
+        let passed = match (self.op, &value) {
+            (Op::Exists, _) => value.is_ok(),
+            (Op::NotExists, _) => value.is_err(),
+            (_, &Err(_)) => false,
+            (Op::Eq, &Ok(ref value)) => self.equals(value),
+            (Op::NotEq, &Ok(ref value)) => !self.equals(value),
+            (Op::Contains, &Ok(ref value)) => as_text(value).contains(&self.expected),
+            (Op::NotContains, &Ok(ref value)) => !as_text(value).contains(&self.expected),
+            (op, &Ok(ref value)) => match (as_number(value), self.expected_number()) {
+                (Some(actual), Some(expected)) => match op {
+                    Op::Lt => actual < expected,
+                    Op::Le => actual <= expected,
+                    Op::Gt => actual > expected,
+                    _ => actual >= expected,
+                },
+                _ => false,
+            },
+        };
+        if passed {
+            return None;
+        }
+
+        let subject = self.subject_name();
+        let expected = match self.op {
+            Op::Exists => format!("{} exists", subject),
+            Op::NotExists => format!("{} doesn't exist", subject),
+            op => format!("{} {} {}", subject, op_text(op), self.expected),
+        };
+        let actual = match value {
+            Ok(ref value) if self.subject == Subject::Time => format!("{} = {}ms", subject, value),
+            Ok(ref value) => format!("{} = {}", subject, value),
+            Err(missing) => missing,
+        };
+        Some(Failure { source: self.source.clone(), expected: expected, actual: actual })
+    }
+
+    /// The value the subject has in the response, or why it has none
+    fn resolve(&self, actual: &Actual) -> Result<Value, String> {
+        match self.subject {
+            Subject::Status => Ok(Value::from(actual.status.as_u16())),
+            Subject::Time => Ok(Value::from(millis(actual.time).round() as u64)),
+            Subject::Header(ref name) => {
+                let values = actual.headers.get_all(name.as_str()).iter()
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+                    .collect::<Vec<_>>();
+                if values.is_empty() {
+                    return Err(format!("there is no {} header", name));
+                }
+                Ok(Value::String(values.join(", ")))
+            },
+            Subject::Body(ref path) if path.is_empty() => Ok(match actual.json {
+                Some(json) => json.clone(),
+                None => Value::String(String::from(actual.text)),
+            }),
+            Subject::Body(ref path) => {
+                let mut value = actual.json.ok_or_else(|| String::from("the body isn't JSON"))?;
+                for (i, key) in path.iter().enumerate() {
+                    let found = match *value {
+                        Value::Object(ref map) => map.get(key),
+                        Value::Array(ref items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                        _ => None,
+                    };
+                    value = match found {
+                        Some(found) => found,
+                        // `length` is only special where it isn't a key of its own
+                        None if key == "length" && i == path.len() - 1 => return match *value {
+                            Value::Array(ref items) => Ok(Value::from(items.len())),
+                            Value::Object(ref map) => Ok(Value::from(map.len())),
+                            Value::String(ref s) => Ok(Value::from(s.chars().count())),
+                            _ => Err(format!("{} has no length", self.path_name(i))),
+                        },
+                        None => return Err(format!("there is no {}", self.path_name(i + 1))),
+                    };
+                }
+                Ok(value.clone())
+            },
+        }
+    }
+
+    /// Strings compare equal to the expected text as it is, so that `body.name=chrome` doesn't
+    /// need quotes, other values to the expected text parsed as JSON
+    fn equals(&self, value: &Value) -> bool {
+        if let Value::String(ref s) = *value {
+            if *s == self.expected {
+                return true;
+            }
+        }
+        match self.subject {
+            Subject::Time => self.expected_number() == as_number(value),
+            _ => serde_json::from_str::<Value>(&self.expected).map(|expected| expected == *value).unwrap_or(false),
+        }
+    }
+
+    fn expected_number(&self) -> Option<f64> {
+        match self.subject {
+            Subject::Time => parse_duration(&self.expected).ok().map(|d| millis(d).round()),
+            _ => self.expected.parse().ok(),
+        }
+    }
+
+    fn subject_name(&self) -> String {
+        match self.subject {
+            Subject::Status => String::from("status"),
+            Subject::Time => String::from("time"),
+            Subject::Header(ref name) => format!("header.{}", name),
+            Subject::Body(ref path) => self.path_name(path.len()),
+        }
+    }
+
+    /// `body` followed by the first `len` keys of the path
+    fn path_name(&self, len: usize) -> String {
+        match self.subject {
+            Subject::Body(ref path) => {
+                let mut name = String::from("body");
+                for key in &path[..len] {
+                    name.push('.');
+                    name.push_str(key);
+                }
+                name
+            },
+            _ => self.subject_name(),
+        }
+    }
+}
+
+fn parse_subject(subject: &str) -> Result<Subject, String> {
+    match subject {
+        "status" => Ok(Subject::Status),
+        "time" => Ok(Subject::Time),
+        "body" => Ok(Subject::Body(Vec::new())),
+        _ if subject.starts_with("header.") && subject.len() > "header.".len() =>
+            Ok(Subject::Header(subject["header.".len()..].to_ascii_lowercase())),
+        _ if subject.starts_with("body.") => {
+            let path = subject["body.".len()..].split('.').map(String::from).collect::<Vec<_>>();
+            if path.iter().any(String::is_empty) {
+                return Err(format!("Empty key in '{}'", subject));
+            }
+            Ok(Subject::Body(path))
+        },
+        _ => Err(format!("Expected status, header.NAME, body, body.PATH or time, got '{}'", subject)),
+    }
+}
+
+fn op_text(op: Op) -> &'static str {
+    OPERATORS.iter().find(|&&(_, o)| o == op).map(|&(text, _)| text).unwrap_or("")
+}
+
+fn as_text(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => s.clone(),
+        ref other => other.to_string(),
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Number(ref n) => n.as_f64(),
+        Value::String(ref s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Checks every expectation and reports the ones which failed to stderr, each as the line
+/// given on the command line followed by what was expected and what came back
+pub fn check_all(expectations: &[Expectation], actual: &Actual, colored: bool) -> io::Result<usize> {
+    let failures = expectations.iter()
+        .filter_map(|expectation| expectation.check(actual))
+        .collect::<Vec<_>>();
+
+    let stderr = io::stderr();
+    let mut out = stderr.lock();
+    let paint = |colour: Colour, text: String| {
+        if colored { colour.paint(text).to_string() } else { text }
+    };
+    for failure in &failures {
+        writeln!(out, "{}", paint(Red, format!("Expectation failed: {}", failure.source)))?;
+        writeln!(out, "{}", paint(Green, format!("  - expected  {}", failure.expected)))?;
+        writeln!(out, "{}", paint(Red, format!("  + actual    {}", failure.actual)))?;
+    }
+    if failures.is_empty() {
+        writeln!(out, "{}", paint(Green, format!("All {} expectations passed", expectations.len())))?;
+    }
+    Ok(failures.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{CONTENT_TYPE, HeaderValue};
+
+    fn check(source: &str, json: &str) -> Option<Failure> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json; charset=utf-8"));
+        let value: Value = serde_json::from_str(json).unwrap();
+        let actual = Actual {
+            status: StatusCode::CREATED,
+            headers: &headers,
+            json: Some(&value),
+            text: json,
+            time: Duration::from_millis(120),
+        };
+        Expectation::parse(source).unwrap().check(&actual)
+    }
+
+    fn passes(source: &str, json: &str) -> bool {
+        check(source, json).is_none()
+    }
+
+    #[test]
+    fn parses_subjects_and_operators() {
+        let e = Expectation::parse("body.items.length >= 2").unwrap();
+        assert_eq!(e.subject, Subject::Body(vec![String::from("items"), String::from("length")]));
+        assert_eq!(e.op, Op::Ge);
+        assert_eq!(e.expected, "2");
+        assert_eq!(Expectation::parse("header.Content-Type~json").unwrap().subject,
+                   Subject::Header(String::from("content-type")));
+        assert_eq!(Expectation::parse("body.id exists").unwrap().op, Op::Exists);
+        assert_eq!(Expectation::parse("body.id !exists").unwrap().op, Op::NotExists);
+    }
+
+    #[test]
+    fn rejects_invalid_expectations() {
+        assert!(Expectation::parse("status").is_err());
+        assert!(Expectation::parse("status<many").is_err());
+        assert!(Expectation::parse("time<soon").is_err());
+        assert!(Expectation::parse("cookies=1").is_err());
+        assert!(Expectation::parse("body..id=1").is_err());
+    }
+
+    #[test]
+    fn checks_status_headers_and_time() {
+        assert!(passes("status=201", "{}"));
+        assert!(passes("status<400", "{}"));
+        assert!(!passes("status=200", "{}"));
+        assert!(passes("header.Content-Type~json", "{}"));
+        assert!(!passes("header.Content-Type!~json", "{}"));
+        assert!(!passes("header.ETag exists", "{}"));
+        assert!(passes("time<500ms", "{}"));
+        assert!(!passes("time<0.1s", "{}"));
+    }
+
+    #[test]
+    fn checks_json_bodies() {
+        let body = r#"{"id": 7, "name": "chrome", "items": [{"id": 1}, {"id": 2}], "length": 3}"#;
+        assert!(passes("body.id exists", body));
+        assert!(passes("body.id=7", body));
+        assert!(passes("body.name=chrome", body));
+        assert!(passes("body.name=\"chrome\"", body));
+        assert!(passes("body.items.length>1", body));
+        assert!(passes("body.items.1.id=2", body));
+        assert!(passes("body.name.length=6", body));
+        // A key of its own wins over the length of the object
+        assert!(passes("body.length=3", body));
+        assert!(passes("body.missing !exists", body));
+        assert!(!passes("body.items.length>2", body));
+    }
+
+    #[test]
+    fn failures_describe_both_sides() {
+        assert_eq!(check("status=200", "{}"), Some(Failure {
+            source: String::from("status=200"),
+            expected: String::from("status = 200"),
+            actual: String::from("status = 201"),
+        }));
+        let failure = check("body.user.id exists", r#"{"user": {}}"#).unwrap();
+        assert_eq!(failure.expected, "body.user.id exists");
+        assert_eq!(failure.actual, "there is no body.user.id");
+        let failure = check("body.name~chrome", r#"{"name": "firefox"}"#).unwrap();
+        assert_eq!(failure.actual, "body.name = \"firefox\"");
+    }
+}
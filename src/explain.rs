@@ -0,0 +1,85 @@
+use ansi_term::Colour::{Blue, Red, Yellow};
+use atty::{self, Stream};
+use clap::{App as ClapApp, AppSettings, Arg};
+
+use std::io::{self, Write};
+
+use request_item::{ParsedItem, RequestItem, RequestItemType, parse_request_item, suggestion};
+
+/// `chrome explain ITEM...` shows how each item is tokenised and where it ends up in the
+/// request. Returns whether all of them were valid.
+pub fn explain(args: Vec<String>) -> io::Result<bool> {
+    let colored = atty::is(Stream::Stdout);
+    let matches = ClapApp::new("chrome explain")
+        .about("Show how request items are parsed and which part of the request they go to")
+        .global_setting(if colored { AppSettings::ColoredHelp } else { AppSettings::ColorNever })
+        .arg(Arg::with_name("ITEM")
+             .help("The request items to explain, as they would be given to a request")
+             .required(true)
+             .multiple(true)
+        )
+        .get_matches_from(args);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let label = |name: &str| {
+        let name = format!("  {:<10}", name);
+        if colored { Blue.paint(name).to_string() } else { name }
+    };
+
+    let mut valid = true;
+    for (i, item) in matches.values_of("ITEM").unwrap().enumerate() {
+        if i > 0 {
+            writeln!(out, "")?;
+        }
+        writeln!(out, "{}", item)?;
+        match parse_request_item(item) {
+            Ok(parsed) => {
+                writeln!(out, "{}{}", label("key"), parsed.item.key)?;
+                writeln!(out, "{}{}  (at byte {})", label("separator"), parsed.separator, parsed.separator_offset)?;
+                writeln!(out, "{}{}", label("value"), parsed.item.value)?;
+                writeln!(out, "{}{}", label("escaped"), escaped(&parsed))?;
+                writeln!(out, "{}{}", label("goes to"), destination(&parsed.item))?;
+                if let Some(hint) = suggestion(&parsed.item) {
+                    let hint = format!("  hint: {}", hint);
+                    writeln!(out, "{}", if colored { Yellow.paint(hint).to_string() } else { hint })?;
+                }
+            },
+            Err(e) => {
+                valid = false;
+                let message = e.to_string();
+                writeln!(out, "{}", if colored { Red.paint(message).to_string() } else { message })?;
+            },
+        }
+    }
+    Ok(valid)
+}
+
+fn escaped(parsed: &ParsedItem) -> String {
+    if parsed.escaped.is_empty() {
+        return String::from("nothing");
+    }
+    parsed.escaped.iter()
+        .map(|&(offset, c)| format!("`{}` at byte {}", c, offset))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn destination(item: &RequestItem) -> String {
+    let (key, value) = (&item.key, &item.value);
+    match item.variant {
+        RequestItemType::HTTPHeader if value.is_empty() =>
+            format!("unsets the header {}, including any default for it", key),
+        RequestItemType::HTTPHeader => format!("the header {}", key),
+        RequestItemType::EmptyHTTPHeader => format!("the header {}, sent with an empty value", key),
+        RequestItemType::FileHTTPHeader => format!("the header {}, with the value read from {}", key, value),
+        RequestItemType::URLParameter => format!("the URL parameter {}", key),
+        RequestItemType::FileURLParameter => format!("the URL parameter {}, with the value read from {}", key, value),
+        RequestItemType::DataField => format!("the field {} of the body, as a string (JSON, or form data with --form)", key),
+        RequestItemType::JsonData => format!("the field {} of the JSON body, as raw JSON", key),
+        RequestItemType::FormFile => format!("the file upload {} of a multipart form, with the file {}", key, value),
+        RequestItemType::FileDataField => format!("the field {} of the body, as a string with the content of {}", key, value),
+        RequestItemType::FileJsonData => format!("the field {} of the JSON body, as the JSON in {}", key, value),
+        RequestItemType::BodyFile => format!("the whole body, with the content of {}", value),
+    }
+}
@@ -0,0 +1,124 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+
+/// Expands a leading `~` to the home directory, and `$VAR` or `${VAR}` anywhere in the path
+/// to the value of the environment variable. Variables which aren't set are left as they
+/// are, so that the error about the missing file still names them.
+pub fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut rest = path;
+
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        if let Some(home) = home_dir() {
+            expanded.push_str(&home);
+            rest = &rest[1..];
+        }
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = if after.starts_with('{') {
+            match after.find('}') {
+                Some(end) => (&after[1..end], end + 1),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+        match env::var(name) {
+            Ok(ref value) if !name.is_empty() => expanded.push_str(value),
+            _ => expanded.push_str(&rest[start..start + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+fn home_dir() -> Option<String> {
+    env::var("HOME").or_else(|_| env::var("USERPROFILE")).ok()
+}
+
+/// Reads the whole of a file, or of stdin for `-`
+pub fn read_file(path: &str) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    if path == "-" {
+        io::stdin().read_to_end(&mut contents)?;
+    } else {
+        File::open(expand_path(path))?.read_to_end(&mut contents)?;
+    }
+    Ok(contents)
+}
+
+/// Reads a file as text, e.g. for `=@` fields
+pub fn read_to_string(path: &str) -> io::Result<String> {
+    String::from_utf8(read_file(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Reads a header or URL parameter value from a file, without the newline editors and
+/// `echo` leave at the end
+pub fn read_value(path: &str) -> io::Result<String> {
+    let mut value = read_to_string(path)?;
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Writes `contents` to a file of its own in the temp directory
+    fn temp_file(name: &str, contents: &str) -> String {
+        let path = env::temp_dir().join(format!("chrome-files-{}-{}", ::std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn tilde_is_the_home_directory() {
+        // HOME is only read, so that other tests don't see it change
+        if let Some(home) = home_dir() {
+            assert_eq!(expand_path("~"), PathBuf::from(&home));
+            assert_eq!(expand_path("~/token"), PathBuf::from(format!("{}/token", home)));
+        }
+        assert_eq!(expand_path("~user/token"), PathBuf::from("~user/token"));
+        assert_eq!(expand_path("a/~/token"), PathBuf::from("a/~/token"));
+    }
+
+    #[test]
+    fn variables_are_expanded() {
+        env::set_var("CHROME_FILES_TEST_DIR", "/secrets");
+        assert_eq!(expand_path("$CHROME_FILES_TEST_DIR/token"), PathBuf::from("/secrets/token"));
+        assert_eq!(expand_path("${CHROME_FILES_TEST_DIR}token"), PathBuf::from("/secretstoken"));
+        assert_eq!(expand_path("a-$CHROME_FILES_TEST_DIR-b"), PathBuf::from("a-/secrets-b"));
+    }
+
+    #[test]
+    fn unset_variables_are_kept() {
+        env::remove_var("CHROME_FILES_TEST_UNSET");
+        assert_eq!(expand_path("$CHROME_FILES_TEST_UNSET/token"), PathBuf::from("$CHROME_FILES_TEST_UNSET/token"));
+        assert_eq!(expand_path("${CHROME_FILES_TEST_UNSET}/token"), PathBuf::from("${CHROME_FILES_TEST_UNSET}/token"));
+        assert_eq!(expand_path("${CHROME_FILES_TEST_UNSET/token"), PathBuf::from("${CHROME_FILES_TEST_UNSET/token"));
+        assert_eq!(expand_path("price$"), PathBuf::from("price$"));
+    }
+
+    #[test]
+    fn values_lose_exactly_one_line_ending() {
+        assert_eq!(read_value(&temp_file("lf", "token\n")).unwrap(), "token");
+        assert_eq!(read_value(&temp_file("crlf", "token\r\n")).unwrap(), "token");
+        assert_eq!(read_value(&temp_file("two", "token\n\n")).unwrap(), "token\n");
+        assert_eq!(read_value(&temp_file("none", "token")).unwrap(), "token");
+        assert_eq!(read_value(&temp_file("cr", "token\r")).unwrap(), "token\r");
+    }
+}
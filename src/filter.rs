@@ -0,0 +1,621 @@
+use serde_json::{Number, Value};
+
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// A parsed `--filter` expression.
+///
+/// The language is a small subset of jq:
+///
+///   .              the input itself
+///   .foo  .["foo"] field access
+///   .[0]  .[-1]    array indexing
+///   .[1:3]         array (or string) slicing
+///   .[]            iteration over all values of an array or object
+///   a | b          feeds every output of `a` into `b`
+///   select(cond)   keeps the input only if `cond` is truthy
+///   keys, length   builtins
+///
+/// Conditions may compare values using `==`, `!=`, `<`, `<=`, `>`, `>=` and be combined
+/// with `and`, `or` and `not`.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Identity,
+    Field(Box<Expr>, String),
+    Index(Box<Expr>, i64),
+    Slice(Box<Expr>, Option<i64>, Option<i64>),
+    Iterate(Box<Expr>),
+    Pipe(Box<Expr>, Box<Expr>),
+    Select(Box<Expr>),
+    Compare(Box<Expr>, CmpOp, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not,
+    Keys,
+    Length,
+    Literal(Value),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Dot,
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Colon,
+    Pipe,
+    Cmp(CmpOp),
+}
+
+pub fn is_filter(v: String) -> Result<(), String> {
+    Filter::parse(&v).map(|_| ())
+}
+
+impl Filter {
+    pub fn parse(expression: &str) -> Result<Filter, String> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser { tokens: tokens, pos: 0 };
+        let expr = parser.pipe()?;
+        match parser.peek() {
+            None => Ok(Filter { expr: expr }),
+            Some(tok) => Err(format!("Unexpected {:?} in filter", tok)),
+        }
+    }
+
+    /// Runs the filter, returning every value it produces.
+    pub fn apply(&self, input: &Value) -> Result<Vec<Value>, String> {
+        eval(&self.expr, input)
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.chars().peekable();
+    while let Some(&chr) = chars.peek() {
+        match chr {
+            ' ' | '\t' | '\n' => { chars.next(); },
+            '.' => { chars.next(); tokens.push(Token::Dot); },
+            '[' => { chars.next(); tokens.push(Token::LBracket); },
+            ']' => { chars.next(); tokens.push(Token::RBracket); },
+            '(' => { chars.next(); tokens.push(Token::LParen); },
+            ')' => { chars.next(); tokens.push(Token::RParen); },
+            ':' => { chars.next(); tokens.push(Token::Colon); },
+            '|' => { chars.next(); tokens.push(Token::Pipe); },
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let followed_by_eq = chars.peek() == Some(&'=');
+                if followed_by_eq {
+                    chars.next();
+                }
+                let op = match (chr, followed_by_eq) {
+                    ('=', true) => CmpOp::Eq,
+                    ('!', true) => CmpOp::Ne,
+                    ('<', false) => CmpOp::Lt,
+                    ('<', true) => CmpOp::Le,
+                    ('>', false) => CmpOp::Gt,
+                    ('>', true) => CmpOp::Ge,
+                    _ => return Err(format!("Unexpected '{}' in filter", chr)),
+                };
+                tokens.push(Token::Cmp(op));
+            },
+            '"' => {
+                chars.next();
+                tokens.push(Token::Str(string_literal(&mut chars)?));
+            },
+            '-' | '0'..='9' => {
+                let mut number = String::new();
+                number.push(chr);
+                chars.next();
+                while let Some(&c) = chars.peek() {
+                    if c.is_digit(10) || c == '.' || c == 'e' || c == 'E' {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let parsed = number.parse::<f64>()
+                    .map_err(|_| format!("Invalid number '{}' in filter", number))?;
+                tokens.push(Token::Num(parsed));
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == '-' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            },
+            c => return Err(format!("Unexpected '{}' in filter", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn string_literal(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(String::from("Unterminated string in filter")),
+            Some('"') => return Ok(string),
+            Some('\\') => match chars.next() {
+                Some('n') => string.push('\n'),
+                Some('t') => string.push('\t'),
+                Some(c) => string.push(c),
+                None => return Err(String::from("Unterminated string in filter")),
+            },
+            Some(c) => string.push(c),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref tok) if *tok == expected => Ok(()),
+            Some(tok) => Err(format!("Expected {:?} but found {:?} in filter", expected, tok)),
+            None => Err(format!("Expected {:?} at the end of filter", expected)),
+        }
+    }
+
+    fn pipe(&mut self) -> Result<Expr, String> {
+        let mut expr = self.or()?;
+        while self.peek() == Some(&Token::Pipe) {
+            self.next();
+            let rhs = self.or()?;
+            expr = Expr::Pipe(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Ident(String::from("or"))) {
+            self.next();
+            let rhs = self.and()?;
+            expr = Expr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.comparison()?;
+        while self.peek() == Some(&Token::Ident(String::from("and"))) {
+            self.next();
+            let rhs = self.comparison()?;
+            expr = Expr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, String> {
+        let lhs = self.term()?;
+        if let Some(Token::Cmp(op)) = self.peek().cloned() {
+            self.next();
+            let rhs = self.term()?;
+            return Ok(Expr::Compare(Box::new(lhs), op, Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Dot) => {
+                let expr = match self.peek().cloned() {
+                    Some(Token::Ident(name)) => {
+                        self.next();
+                        Expr::Field(Box::new(Expr::Identity), name)
+                    },
+                    Some(Token::Str(name)) => {
+                        self.next();
+                        Expr::Field(Box::new(Expr::Identity), name)
+                    },
+                    _ => Expr::Identity,
+                };
+                self.suffixes(expr)
+            },
+            Some(Token::Ident(name)) => match name.as_str() {
+                "select" => {
+                    self.expect(Token::LParen)?;
+                    let cond = self.pipe()?;
+                    self.expect(Token::RParen)?;
+                    Ok(Expr::Select(Box::new(cond)))
+                },
+                "keys" => Ok(Expr::Keys),
+                "length" => Ok(Expr::Length),
+                "not" => Ok(Expr::Not),
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ => Err(format!("Unknown function '{}' in filter", name)),
+            },
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Num(n)) => Ok(Expr::Literal(number(n))),
+            Some(Token::LParen) => {
+                let expr = self.pipe()?;
+                self.expect(Token::RParen)?;
+                self.suffixes(expr)
+            },
+            Some(tok) => Err(format!("Unexpected {:?} in filter", tok)),
+            None => Err(String::from("Unexpected end of filter")),
+        }
+    }
+
+    /// Parses any trailing `.field`, `[..]` accesses following a term.
+    fn suffixes(&mut self, mut expr: Expr) -> Result<Expr, String> {
+        loop {
+            match self.peek().cloned() {
+                Some(Token::Dot) => {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Ident(name)) | Some(Token::Str(name)) => {
+                            expr = Expr::Field(Box::new(expr), name);
+                        },
+                        Some(Token::LBracket) => expr = self.bracket(expr)?,
+                        _ => return Err(String::from("Expected a field name after '.' in filter")),
+                    }
+                },
+                Some(Token::LBracket) => {
+                    self.next();
+                    expr = self.bracket(expr)?;
+                },
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    /// Parses the inside of `[..]`, the opening bracket having been consumed already.
+    fn bracket(&mut self, expr: Expr) -> Result<Expr, String> {
+        let target = Box::new(expr);
+        let result = match self.next() {
+            Some(Token::RBracket) => return Ok(Expr::Iterate(target)),
+            Some(Token::Str(name)) => Expr::Field(target, name),
+            Some(Token::Num(n)) => {
+                if self.peek() == Some(&Token::Colon) {
+                    self.next();
+                    let end = self.slice_bound()?;
+                    Expr::Slice(target, Some(n as i64), end)
+                } else {
+                    Expr::Index(target, n as i64)
+                }
+            },
+            Some(Token::Colon) => {
+                let end = self.slice_bound()?;
+                Expr::Slice(target, None, end)
+            },
+            Some(tok) => return Err(format!("Unexpected {:?} inside [] in filter", tok)),
+            None => return Err(String::from("Unterminated [] in filter")),
+        };
+        self.expect(Token::RBracket)?;
+        Ok(result)
+    }
+
+    fn slice_bound(&mut self) -> Result<Option<i64>, String> {
+        match self.peek().cloned() {
+            Some(Token::Num(n)) => {
+                self.next();
+                Ok(Some(n as i64))
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+fn eval(expr: &Expr, input: &Value) -> Result<Vec<Value>, String> {
+    match *expr {
+        Expr::Identity => Ok(vec![input.clone()]),
+        Expr::Literal(ref value) => Ok(vec![value.clone()]),
+        Expr::Field(ref target, ref name) => each(target, input, |v| match *v {
+            Value::Object(ref map) => Ok(vec![map.get(name).cloned().unwrap_or(Value::Null)]),
+            Value::Null => Ok(vec![Value::Null]),
+            ref other => Err(format!("Cannot index {} with \"{}\"", type_name(other), name)),
+        }),
+        Expr::Index(ref target, index) => each(target, input, |v| match *v {
+            Value::Array(ref array) => Ok(vec![
+                resolve_index(index, array.len())
+                    .and_then(|i| array.get(i).cloned())
+                    .unwrap_or(Value::Null)
+            ]),
+            Value::Null => Ok(vec![Value::Null]),
+            ref other => Err(format!("Cannot index {} with number", type_name(other))),
+        }),
+        Expr::Slice(ref target, start, end) => each(target, input, |v| {
+            let len = match *v {
+                Value::Array(ref array) => array.len(),
+                Value::String(ref s) => s.chars().count(),
+                Value::Null => return Ok(vec![Value::Null]),
+                ref other => return Err(format!("Cannot slice {}", type_name(other))),
+            };
+            let from = start.map(|s| clamp_index(s, len)).unwrap_or(0);
+            let to = end.map(|e| clamp_index(e, len)).unwrap_or(len);
+            let to = if to < from { from } else { to };
+            Ok(vec![match *v {
+                Value::Array(ref array) => Value::Array(array[from..to].to_vec()),
+                Value::String(ref s) => Value::String(s.chars().skip(from).take(to - from).collect()),
+                _ => unreachable!(),
+            }])
+        }),
+        Expr::Iterate(ref target) => each(target, input, |v| match *v {
+            Value::Array(ref array) => Ok(array.clone()),
+            Value::Object(ref map) => Ok(map.values().cloned().collect()),
+            ref other => Err(format!("Cannot iterate over {}", type_name(other))),
+        }),
+        Expr::Pipe(ref lhs, ref rhs) => each(lhs, input, |v| eval(rhs, v)),
+        Expr::Select(ref cond) => {
+            let mut outputs = Vec::new();
+            for result in eval(cond, input)? {
+                if is_truthy(&result) {
+                    outputs.push(input.clone());
+                }
+            }
+            Ok(outputs)
+        },
+        Expr::Compare(ref lhs, op, ref rhs) => {
+            let mut outputs = Vec::new();
+            for r in eval(rhs, input)? {
+                for l in eval(lhs, input)? {
+                    outputs.push(Value::Bool(compare(&l, op, &r)));
+                }
+            }
+            Ok(outputs)
+        },
+        Expr::And(ref lhs, ref rhs) => each(lhs, input, |l| {
+            if !is_truthy(l) {
+                return Ok(vec![Value::Bool(false)]);
+            }
+            Ok(eval(rhs, input)?.iter().map(|r| Value::Bool(is_truthy(r))).collect())
+        }),
+        Expr::Or(ref lhs, ref rhs) => each(lhs, input, |l| {
+            if is_truthy(l) {
+                return Ok(vec![Value::Bool(true)]);
+            }
+            Ok(eval(rhs, input)?.iter().map(|r| Value::Bool(is_truthy(r))).collect())
+        }),
+        Expr::Not => Ok(vec![Value::Bool(!is_truthy(input))]),
+        Expr::Keys => match *input {
+            Value::Object(ref map) => {
+                let mut keys: Vec<String> = map.keys().cloned().collect();
+                keys.sort();
+                Ok(vec![Value::Array(keys.into_iter().map(Value::String).collect())])
+            },
+            Value::Array(ref array) => Ok(vec![Value::Array((0..array.len()).map(|i| Value::from(i as u64)).collect())]),
+            ref other => Err(format!("{} has no keys", type_name(other))),
+        },
+        Expr::Length => Ok(vec![match *input {
+            Value::Null => Value::from(0),
+            Value::Bool(_) => return Err(String::from("boolean has no length")),
+            Value::Number(ref n) => number(n.as_f64().unwrap_or(0.0).abs()),
+            Value::String(ref s) => Value::from(s.chars().count() as u64),
+            Value::Array(ref array) => Value::from(array.len() as u64),
+            Value::Object(ref map) => Value::from(map.len() as u64),
+        }]),
+    }
+}
+
+/// Evaluates `target` and applies `f` to each of its outputs, concatenating the results.
+fn each<F>(target: &Expr, input: &Value, f: F) -> Result<Vec<Value>, String>
+    where F: Fn(&Value) -> Result<Vec<Value>, String>
+{
+    let mut outputs = Vec::new();
+    for value in eval(target, input)? {
+        outputs.extend(f(&value)?);
+    }
+    Ok(outputs)
+}
+
+fn resolve_index(index: i64, len: usize) -> Option<usize> {
+    if index < 0 {
+        let from_end = (-index) as usize;
+        if from_end > len { None } else { Some(len - from_end) }
+    } else {
+        Some(index as usize)
+    }
+}
+
+fn clamp_index(index: i64, len: usize) -> usize {
+    let resolved = if index < 0 { len as i64 + index } else { index };
+    if resolved < 0 {
+        0
+    } else if resolved as usize > len {
+        len
+    } else {
+        resolved as usize
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match *value {
+        Value::Null | Value::Bool(false) => false,
+        _ => true,
+    }
+}
+
+fn number(n: f64) -> Value {
+    if n.fract() == 0.0 && n.abs() < (i64::max_value() as f64) {
+        Value::from(n as i64)
+    } else {
+        Number::from_f64(n).map(Value::Number).unwrap_or(Value::Null)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// jq's ordering: null < false < true < numbers < strings < arrays < objects
+fn type_rank(value: &Value) -> u8 {
+    match *value {
+        Value::Null => 0,
+        Value::Bool(false) => 1,
+        Value::Bool(true) => 2,
+        Value::Number(_) => 3,
+        Value::String(_) => 4,
+        Value::Array(_) => 5,
+        Value::Object(_) => 6,
+    }
+}
+
+fn compare(lhs: &Value, op: CmpOp, rhs: &Value) -> bool {
+    let ordering = match (lhs, rhs) {
+        (&Value::Number(ref l), &Value::Number(ref r)) => {
+            l.as_f64().partial_cmp(&r.as_f64()).unwrap_or(Ordering::Equal)
+        },
+        (&Value::String(ref l), &Value::String(ref r)) => l.cmp(r),
+        (l, r) if type_rank(l) != type_rank(r) => type_rank(l).cmp(&type_rank(r)),
+        (l, r) => {
+            // Arrays and objects can only be compared for equality
+            return match op {
+                CmpOp::Eq => l == r,
+                CmpOp::Ne => l != r,
+                _ => false,
+            };
+        },
+    };
+    match op {
+        CmpOp::Eq => ordering == Ordering::Equal,
+        CmpOp::Ne => ordering != Ordering::Equal,
+        CmpOp::Lt => ordering == Ordering::Less,
+        CmpOp::Le => ordering != Ordering::Greater,
+        CmpOp::Gt => ordering == Ordering::Greater,
+        CmpOp::Ge => ordering != Ordering::Less,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    fn run(filter: &str, input: &str) -> Result<Vec<Value>, String> {
+        Filter::parse(filter)?.apply(&serde_json::from_str(input).unwrap())
+    }
+
+    /// The outputs a filter is expected to produce, written as a JSON array
+    fn outputs(json: &str) -> Result<Vec<Value>, String> {
+        Ok(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn field_access() {
+        let input = r#"{"a": {"b": 1}, "a b": 2}"#;
+        assert_eq!(run(".", "1"), outputs("[1]"));
+        assert_eq!(run(".a.b", input), outputs("[1]"));
+        assert_eq!(run(r#".["a b"]"#, input), outputs("[2]"));
+        assert_eq!(run(r#"."a b""#, input), outputs("[2]"));
+        assert_eq!(run(".missing.deeper", input), outputs("[null]"));
+    }
+
+    #[test]
+    fn indexing_and_slicing() {
+        let input = "[1, 2, 3]";
+        assert_eq!(run(".[0]", input), outputs("[1]"));
+        assert_eq!(run(".[-1]", input), outputs("[3]"));
+        assert_eq!(run(".[5]", input), outputs("[null]"));
+        assert_eq!(run(".[-5]", input), outputs("[null]"));
+        assert_eq!(run(".[1:3]", input), outputs("[[2, 3]]"));
+        assert_eq!(run(".[:-1]", input), outputs("[[1, 2]]"));
+        assert_eq!(run(".[-2:]", input), outputs("[[2, 3]]"));
+        assert_eq!(run(".[2:1]", input), outputs("[[]]"));
+        assert_eq!(run(".[1:3]", r#""hello""#), outputs(r#"["el"]"#));
+    }
+
+    #[test]
+    fn iteration_and_pipes() {
+        assert_eq!(run(".[]", "[1, 2]"), outputs("[1, 2]"));
+        assert_eq!(run(".[]", r#"{"a": 1, "b": 2}"#), outputs("[1, 2]"));
+        assert_eq!(run(".items[] | .name", r#"{"items": [{"name": "a"}, {"name": "b"}]}"#), outputs(r#"["a", "b"]"#));
+        assert_eq!(run(".a | .b | .c", r#"{"a": {"b": {"c": true}}}"#), outputs("[true]"));
+    }
+
+    #[test]
+    fn select_with_comparisons() {
+        let input = r#"[{"name": "a", "age": 20, "admin": true}, {"name": "b", "age": 10, "admin": false}]"#;
+        assert_eq!(run(".[] | select(.age >= 18) | .name", input), outputs(r#"["a"]"#));
+        assert_eq!(run(".[] | select(.age < 18) | .name", input), outputs(r#"["b"]"#));
+        assert_eq!(run(r#".[] | select(.name != "a") | .age"#, input), outputs("[10]"));
+        assert_eq!(run(".[] | select(.age > 5 and (.admin | not)) | .name", input), outputs(r#"["b"]"#));
+        assert_eq!(run(".[] | select(.age == 10 or .admin) | .name", input), outputs(r#"["a", "b"]"#));
+        // Values of different types are ordered like jq does
+        assert_eq!(run(r#"null < false and false < 1 and 1 < "a""#, "null"), outputs("[true]"));
+    }
+
+    #[test]
+    fn keys_and_length() {
+        assert_eq!(run("keys", r#"{"b": 1, "a": 2}"#), outputs(r#"[["a", "b"]]"#));
+        assert_eq!(run("keys", r#"["x", "y"]"#), outputs("[[0, 1]]"));
+        assert_eq!(run("length", "[1, 2, 3]"), outputs("[3]"));
+        assert_eq!(run("length", r#""héllo""#), outputs("[5]"));
+        assert_eq!(run("length", r#"{"a": 1}"#), outputs("[1]"));
+        assert_eq!(run("length", "null"), outputs("[0]"));
+        assert_eq!(run("length", "-5"), outputs("[5]"));
+        assert_eq!(run(".items | length", r#"{"items": [1, 2]}"#), outputs("[2]"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Filter::parse(".[").unwrap_err(), "Unterminated [] in filter");
+        assert_eq!(Filter::parse(".a ==").unwrap_err(), "Unexpected end of filter");
+        assert_eq!(Filter::parse("select(.a").unwrap_err(), "Expected RParen at the end of filter");
+        assert_eq!(Filter::parse("foo").unwrap_err(), "Unknown function 'foo' in filter");
+        assert_eq!(Filter::parse(r#".["a"#).unwrap_err(), "Unterminated string in filter");
+        assert_eq!(Filter::parse(".a )").unwrap_err(), "Unexpected RParen in filter");
+        assert_eq!(Filter::parse(".a = 1").unwrap_err(), "Unexpected '=' in filter");
+        assert_eq!(Filter::parse(".a.").unwrap_err(), "Expected a field name after '.' in filter");
+    }
+
+    #[test]
+    fn type_errors() {
+        assert_eq!(run(".[0]", r#"{"a": 1}"#).unwrap_err(), "Cannot index object with number");
+        assert_eq!(run(".a", "[1]").unwrap_err(), r#"Cannot index array with "a""#);
+        assert_eq!(run(".[]", "1").unwrap_err(), "Cannot iterate over number");
+        assert_eq!(run(".[1:]", "true").unwrap_err(), "Cannot slice boolean");
+        assert_eq!(run("keys", r#""a""#).unwrap_err(), "string has no keys");
+        assert_eq!(run("length", "true").unwrap_err(), "boolean has no length");
+    }
+}
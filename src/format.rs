@@ -0,0 +1,303 @@
+use http::header::HeaderMap;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde_json::{self, Map, Value};
+use serde_json::ser::PrettyFormatter;
+
+use std::collections::HashSet;
+use std::mem;
+
+/// Options controlling how JSON bodies and headers are formatted, set with
+/// `--format-options=json.sort_keys:true,json.indent:4,json.ascii:false,headers.sort:true`
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOptions {
+    pub json_sort_keys: bool,
+    pub json_indent: usize,
+    pub json_ascii: bool,
+    pub headers_sort: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            json_sort_keys: false,
+            json_indent: 2,
+            json_ascii: false,
+            headers_sort: false,
+        }
+    }
+}
+
+pub fn is_format_options(v: String) -> Result<(), String> {
+    FormatOptions::default().update(&v)
+}
+
+/// Splits `key:value,key:value` into its pairs.
+pub fn parse_options(spec: &str) -> Result<Vec<(String, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|option| !option.is_empty())
+        .map(|option| {
+            let mut splitted = option.splitn(2, ':');
+            let key = splitted.next().unwrap_or("").trim();
+            match splitted.next() {
+                Some(value) if !key.is_empty() => Ok((String::from(key), String::from(value.trim()))),
+                _ => Err(format!("Expected an option of the form key:value, got '{}'", option)),
+            }
+        })
+        .collect()
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("Expected true or false for {}, got '{}'", key, value)),
+    }
+}
+
+impl FormatOptions {
+    /// Applies the options in `spec` on top of the current ones.
+    pub fn update(&mut self, spec: &str) -> Result<(), String> {
+        for (key, value) in parse_options(spec)? {
+            match key.as_str() {
+                "json.sort_keys" => self.json_sort_keys = parse_bool(&key, &value)?,
+                "json.ascii" => self.json_ascii = parse_bool(&key, &value)?,
+                "headers.sort" => self.headers_sort = parse_bool(&key, &value)?,
+                "json.indent" => {
+                    self.json_indent = value.parse::<usize>()
+                        .map_err(|_| format!("Expected a number for {}, got '{}'", key, value))?;
+                },
+                _ => return Err(format!("Unknown format option '{}'", key)),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn format_json<T: Serialize>(&self, value: &T) -> Result<String, serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        let sorted = Sorted { value: &value, sort_keys: self.json_sort_keys };
+
+        let mut buf = Vec::new();
+        if self.json_indent == 0 {
+            serde_json::to_writer(&mut buf, &sorted)?;
+        } else {
+            let indent = vec![b' '; self.json_indent];
+            let mut ser = serde_json::Serializer::with_formatter(&mut buf, PrettyFormatter::with_indent(&indent));
+            sorted.serialize(&mut ser)?;
+        }
+        // serde_json only ever writes valid UTF-8
+        let json = String::from_utf8(buf).unwrap();
+
+        if self.json_ascii {
+            Ok(escape_non_ascii(&json))
+        } else {
+            Ok(json)
+        }
+    }
+
+    /// Formats a value like `format_json` (but always indented), one line at a time along with
+    /// the JSON pointer of the value starting on the line. Lines which only close an object or
+    /// array have none.
+    pub fn format_json_lines(&self, value: &Value) -> Vec<(Option<String>, String)> {
+        let mut lines = Vec::new();
+        self.push_json_lines(value, String::new(), String::new(), "", 0, &mut lines);
+        if self.json_ascii {
+            for line in &mut lines {
+                line.1 = escape_non_ascii(&line.1);
+            }
+        }
+        lines
+    }
+
+    fn push_json_lines(&self, value: &Value, pointer: String, prefix: String, suffix: &str, level: usize,
+                       lines: &mut Vec<(Option<String>, String)>) {
+        let width = if self.json_indent == 0 { 2 } else { self.json_indent };
+        let indent = " ".repeat(width * (level + 1));
+        let closing = " ".repeat(width * level);
+        match *value {
+            Value::Object(ref map) if !map.is_empty() => {
+                let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+                if self.json_sort_keys {
+                    entries.sort_by_key(|&(key, _)| key);
+                }
+                lines.push((Some(pointer.clone()), format!("{}{{", prefix)));
+                for (i, &(key, value)) in entries.iter().enumerate() {
+                    // serde_json can always serialize a string
+                    let prefix = format!("{}{}: ", indent, serde_json::to_string(key).unwrap());
+                    let comma = if i + 1 < entries.len() { "," } else { "" };
+                    let pointer = format!("{}/{}", pointer, pointer_segment(key));
+                    self.push_json_lines(value, pointer, prefix, comma, level + 1, lines);
+                }
+                lines.push((None, format!("{}}}{}", closing, suffix)));
+            },
+            Value::Array(ref items) if !items.is_empty() => {
+                lines.push((Some(pointer.clone()), format!("{}[", prefix)));
+                for (i, value) in items.iter().enumerate() {
+                    let comma = if i + 1 < items.len() { "," } else { "" };
+                    self.push_json_lines(value, format!("{}/{}", pointer, i), indent.clone(), comma, level + 1, lines);
+                }
+                lines.push((None, format!("{}]{}", closing, suffix)));
+            },
+            ref other => lines.push((Some(pointer), format!("{}{}{}", prefix, other, suffix))),
+        }
+    }
+
+    /// Formats headers one per line as `Name: value`
+    pub fn format_headers(&self, headers: &HeaderMap) -> String {
+        let mut pairs: Vec<(&str, &str)> = headers.iter()
+            .map(|(key, value)| (key.as_str(), value.to_str().unwrap_or("")))
+            .collect();
+        if self.headers_sort {
+            // A stable sort keeps repeated headers in the order they were sent
+            pairs.sort_by_key(|&(key, _)| key);
+        }
+        let mut formatted = String::new();
+        for (key, value) in pairs {
+            formatted.push_str(&format!("{}: {}\n", key, value));
+        }
+        formatted
+    }
+}
+
+/// Serializes a `Value`, optionally sorting the keys of every object on the way.
+struct Sorted<'a> {
+    value: &'a Value,
+    sort_keys: bool,
+}
+
+impl<'a> Serialize for Sorted<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self.value {
+            Value::Object(ref map) => {
+                let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+                if self.sort_keys {
+                    entries.sort_by_key(|&(key, _)| key);
+                }
+                let mut ser_map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    ser_map.serialize_entry(key, &Sorted { value: value, sort_keys: self.sort_keys })?;
+                }
+                ser_map.end()
+            },
+            Value::Array(ref array) => {
+                let items: Vec<Sorted> = array.iter()
+                    .map(|value| Sorted { value: value, sort_keys: self.sort_keys })
+                    .collect();
+                items.serialize(serializer)
+            },
+            ref other => other.serialize(serializer),
+        }
+    }
+}
+
+/// Builds a JSON object from fields in the order they were given. A key which is repeated
+/// becomes an array of all of its values.
+pub fn json_object<I, V>(fields: I) -> Map<String, Value>
+    where I: IntoIterator<Item = (String, V)>, V: Into<Value>
+{
+    let mut object = Map::new();
+    let mut repeated = HashSet::new();
+    for (key, value) in fields {
+        let value = value.into();
+        // Updated in place, as removing a key would move it to the end
+        if let Some(existing) = object.get_mut(&key) {
+            match *existing {
+                Value::Array(ref mut values) if repeated.contains(&key) => values.push(value),
+                _ => {
+                    let first = mem::replace(existing, Value::Null);
+                    *existing = Value::Array(vec![first, value]);
+                    repeated.insert(key);
+                },
+            }
+            continue;
+        }
+        object.insert(key, value);
+    }
+    object
+}
+
+/// Headers as a JSON object, with the values of repeated headers in arrays
+pub fn headers_object(headers: &HeaderMap) -> Map<String, Value> {
+    json_object(headers.iter().map(|(name, value)| {
+        (String::from(name.as_str()), String::from_utf8_lossy(value.as_bytes()).into_owned())
+    }))
+}
+
+/// A key as it appears in a JSON pointer, with `~` and `/` escaped
+pub fn pointer_segment(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Escapes every non-ascii character as `\uXXXX`. This is only valid because json can't
+/// contain non-ascii characters outside of strings.
+fn escape_non_ascii(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for chr in json.chars() {
+        if chr.is_ascii() {
+            escaped.push(chr);
+        } else {
+            let mut units = [0u16; 2];
+            for unit in chr.encode_utf16(&mut units).iter() {
+                escaped.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(spec: &str) -> FormatOptions {
+        let mut options = FormatOptions::default();
+        options.update(spec).unwrap();
+        options
+    }
+
+    #[test]
+    fn options_are_applied_on_top_of_the_defaults() {
+        let options = options("json.sort_keys:true, json.indent:4,headers.sort:true,");
+        assert!(options.json_sort_keys);
+        assert_eq!(options.json_indent, 4);
+        assert!(!options.json_ascii);
+        assert!(options.headers_sort);
+    }
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        let mut options = FormatOptions::default();
+        assert_eq!(options.update("json.indent"),
+                   Err(String::from("Expected an option of the form key:value, got 'json.indent'")));
+        assert_eq!(options.update("json.indent:-1"),
+                   Err(String::from("Expected a number for json.indent, got '-1'")));
+        assert_eq!(options.update("json.ascii:yes"),
+                   Err(String::from("Expected true or false for json.ascii, got 'yes'")));
+        assert_eq!(options.update("xml.indent:2"), Err(String::from("Unknown format option 'xml.indent'")));
+    }
+
+    #[test]
+    fn keys_keep_their_order_unless_sorted() {
+        // Relies on serde_json's preserve_order feature
+        let value: Value = serde_json::from_str(r#"{"b": 1, "a": {"d": 2, "c": 3}}"#).unwrap();
+        assert_eq!(options("json.indent:0").format_json(&value).unwrap(),
+                   r#"{"b":1,"a":{"d":2,"c":3}}"#);
+        assert_eq!(options("json.indent:0,json.sort_keys:true").format_json(&value).unwrap(),
+                   r#"{"a":{"c":3,"d":2},"b":1}"#);
+    }
+
+    #[test]
+    fn indentation_follows_the_option() {
+        let value: Value = serde_json::from_str(r#"{"a": [1]}"#).unwrap();
+        assert_eq!(options("json.indent:4").format_json(&value).unwrap(),
+                   "{\n    \"a\": [\n        1\n    ]\n}");
+    }
+
+    #[test]
+    fn ascii_escapes_everything_else() {
+        let value = Value::String(String::from("café 🦀"));
+        assert_eq!(options("json.ascii:true").format_json(&value).unwrap(),
+                   r#""caf\u00e9 \ud83e\udd80""#);
+        assert_eq!(options("").format_json(&value).unwrap(), "\"café 🦀\"");
+    }
+}
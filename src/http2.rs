@@ -0,0 +1,113 @@
+use actix_web::actix::Arbiter;
+use actix_web::error::PayloadError;
+use actix_web::http::{Request, Version};
+use actix_web::http::header::{self, HeaderName};
+use bytes::Bytes;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use futures::Stream;
+use futures::future::{self, Future};
+use h2::client;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use std::io::Read;
+
+use errors::ChromeError;
+use request::PreparedRequest;
+use response::{MAX_BODY_SIZE, Response};
+
+/// Headers which only make sense for a single HTTP/1.1 connection and are rejected by
+/// HTTP/2. `Host` is replaced by the `:authority` pseudo header, which h2 takes from the url.
+static CONNECTION_HEADERS: [&'static str; 6] = [
+    "connection", "host", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade",
+];
+
+/// Sends `prepared` over `io` with HTTP/2. The connection itself is driven on the current
+/// arbiter until the server closes it or we drop the last handle to it.
+pub fn send<T>(io: T, prepared: PreparedRequest) -> Box<Future<Item = Response, Error = ChromeError>>
+    where T: AsyncRead + AsyncWrite + 'static
+{
+    let request = match build_request(&prepared) {
+        Ok(request) => request,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let payload = prepared.payload;
+
+    Box::new(client::handshake(io)
+        .from_err()
+        .and_then(|(send_request, connection)| {
+            Arbiter::spawn(connection.map_err(|_| ()));
+            send_request.ready().from_err()
+        })
+        .and_then(move |mut send_request| {
+            let end_of_stream = payload.is_empty();
+            let (response, mut stream) = send_request.send_request(request, end_of_stream)?;
+            if !end_of_stream {
+                stream.send_data(payload, true)?;
+            }
+            Ok(response)
+        })
+        .and_then(|response| response.from_err())
+        .map(|response| {
+            let (parts, mut body) = response.into_parts();
+            let encoding = parts.headers.get(header::CONTENT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().to_lowercase());
+
+            // Data we've read has to be handed back as flow control capacity, or the
+            // server stops sending once the window is full
+            let mut release = body.release_capacity().clone();
+            let body = body
+                .from_err()
+                .fold(Vec::new(), move |mut bytes, chunk: Bytes| {
+                    let _ = release.release_capacity(chunk.len());
+                    if bytes.len() + chunk.len() > MAX_BODY_SIZE {
+                        return Err(ChromeError::from(PayloadError::Overflow));
+                    }
+                    bytes.extend_from_slice(&chunk);
+                    Ok(bytes)
+                })
+                .and_then(move |bytes| decompress(encoding.as_ref().map(String::as_str), bytes));
+
+            Response {
+                version: Version::HTTP_2,
+                status: parts.status,
+                headers: parts.headers,
+                body: Box::new(body),
+            }
+        }))
+}
+
+fn build_request(prepared: &PreparedRequest) -> Result<Request<()>, ChromeError> {
+    let mut builder = Request::builder();
+    builder.method(prepared.method.clone())
+        .uri(prepared.url.as_str())
+        .version(Version::HTTP_2);
+    for (key, value) in prepared.headers.iter() {
+        if !is_connection_header(key) {
+            builder.header(key.clone(), value.clone());
+        }
+    }
+    builder.body(()).map_err(|error| ChromeError::HeaderParseError { error: error })
+}
+
+/// actix decompresses HTTP/1.1 bodies for us, so the same is done here for the encodings
+/// we ask for in `Accept-Encoding`. The decompressed body is held to the same limit as the
+/// compressed one, as a small body can expand to far more.
+fn decompress(encoding: Option<&str>, bytes: Vec<u8>) -> Result<Bytes, ChromeError> {
+    let mut decoded = Vec::new();
+    // One byte over the limit is enough to know it was exceeded
+    let limit = MAX_BODY_SIZE as u64 + 1;
+    match encoding {
+        Some("gzip") | Some("x-gzip") => { GzDecoder::new(&bytes[..]).take(limit).read_to_end(&mut decoded)?; },
+        Some("deflate") => { ZlibDecoder::new(&bytes[..]).take(limit).read_to_end(&mut decoded)?; },
+        _ => return Ok(Bytes::from(bytes)),
+    }
+    if decoded.len() > MAX_BODY_SIZE {
+        return Err(ChromeError::from(PayloadError::Overflow));
+    }
+    Ok(Bytes::from(decoded))
+}
+
+fn is_connection_header(name: &HeaderName) -> bool {
+    CONNECTION_HEADERS.contains(&name.as_str())
+}
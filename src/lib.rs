@@ -0,0 +1,136 @@
+//! The request item syntax, request building and pretty printing behind `chrome`, for
+//! use in other tools.
+//!
+//! A request is described by a [`RequestSpec`](request/struct.RequestSpec.html), built
+//! from HTTPie-style items such as `name=chrome` or `X-Api-Key:@~/.key`. Preparing it
+//! gives a [`PreparedRequest`](request/struct.PreparedRequest.html) which can be echoed
+//! with a [`Printer`](output/struct.Printer.html) and sent.
+
+#[macro_use] extern crate clap;
+#[macro_use] extern crate failure_derive;
+#[macro_use] extern crate lazy_static;
+#[macro_use] extern crate serde_derive;
+
+extern crate actix_web;
+extern crate ansi_term;
+extern crate atty;
+extern crate base64;
+extern crate bytes;
+extern crate console;
+extern crate encoding;
+extern crate failure;
+extern crate flate2;
+extern crate futures;
+extern crate h2;
+extern crate http;
+extern crate mime;
+extern crate openssl;
+extern crate rand;
+extern crate regex;
+extern crate serde;
+extern crate serde_json;
+extern crate serde_urlencoded;
+extern crate syntect;
+extern crate tokio_io;
+extern crate tokio_openssl;
+extern crate tokio_tcp;
+extern crate tokio_timer;
+#[cfg(unix)]
+extern crate tokio_uds;
+extern crate url;
+
+mod batch;
+mod bench;
+pub mod cassette;
+mod charset;
+mod cli;
+pub mod color;
+mod config_file;
+mod connection;
+pub mod errors;
+pub mod expect;
+mod explain;
+mod files;
+pub mod filter;
+pub mod format;
+mod http2;
+pub mod request;
+pub mod request_item;
+pub mod response;
+mod retry;
+pub mod schema;
+mod serve;
+pub mod output;
+pub mod timing;
+
+use actix_web::actix;
+use futures::future::Future;
+
+use std::env;
+use std::process;
+
+use batch::batch;
+use bench::bench;
+use cli::App;
+use errors::handle_error;
+use explain::explain;
+use request::make_request;
+use response::process_response;
+use serve::serve;
+
+pub use connection::HttpVersion;
+pub use errors::ChromeError;
+pub use output::{Body, PrintOptions, Printer};
+pub use request::{BodyType, PreparedRequest, RequestOptions, RequestSpec};
+pub use request_item::{ItemParseError, RequestItem, RequestItemType, get_request_item, parse_request_item};
+pub use response::ResponseOptions;
+pub use retry::RetryPolicy;
+pub use serve::app as serve_app;
+
+/// Runs the `chrome` command line and exits with its status: 0 on full success, 1 if the
+/// request failed or got an unsuccessful response and 4 if it didn't meet the `--expect`
+/// assertions or a schema.
+pub fn run() {
+    // Subcommands are dispatched before the request arguments are parsed, as clap would
+    // otherwise mistake methods such as `get` for misspelt subcommands
+    let args: Vec<String> = env::args().collect();
+    let subcommand = match args.get(1).map(String::as_str) {
+        Some("batch") => Some(batch(args[1..].to_vec())),
+        Some("bench") => Some(bench(args[1..].to_vec())),
+        Some("explain") => Some(explain(args[1..].to_vec()).map_err(ChromeError::from)),
+        Some("serve") => Some(serve(args[1..].to_vec()).map(|_| true).map_err(ChromeError::from)),
+        _ => None,
+    };
+    if let Some(result) = subcommand {
+        let ok = result.unwrap_or_else(|e| {
+            handle_error(e);
+            false
+        });
+        process::exit(if ok { 0 } else { 1 });
+    }
+
+    let app = App::new();
+    actix::run(move || {
+        let config = app.config().unwrap_or_else(|e| {
+            let e = handle_error(e);
+            actix::System::current().stop();
+            process::exit(e.exit_code());
+        });
+        make_request(&config.request, &config.printer, &config.request_options)
+            .and_then(move |(response, timing)| {           // <- server http response
+                process_response(&config.printer, &config.response_options, response, timing)
+            })
+            .map(|v| {
+                actix::System::current().stop();
+                if v {
+                    process::exit(0);
+                }
+                process::exit(1);
+            })
+            .map_err(|e| {
+                let e = handle_error(e);
+                actix::System::current().stop();
+                process::exit(e.exit_code());
+            })
+    })
+}
@@ -1,56 +1,389 @@
+use actix_web::http::Version;
+use bytes::Bytes;
 use syntect::easy::HighlightLines;
-use syntect::highlighting::Style;
-use syntect::util::as_24_bit_terminal_escaped;
+use syntect::highlighting::{Style, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::parsing::syntax_definition::SyntaxDefinition;
 
-use cli::{get_syntax_set, get_theme_set};
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::rc::Rc;
 
+use color::{ColorDepth, as_terminal_escaped};
+use format::FormatOptions;
+use request::PreparedRequest;
+
+static NEW_LINES: bool = false;
+
+#[derive(Clone)]
 pub enum Body {
     Empty,
     Form(String),
     Json(String),
+    /// A binary body which is only summarised, with its size and mime type
+    Binary(usize, Option<String>),
+    /// A body which is written out byte for byte
+    Raw(Bytes),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PagingMode {
+    Always,
+    QuietIfFits,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputWrap {
+    Character,
+    None,
+}
+
+// Used to indent continuation lines without allocating
+static SPACES: &'static str = "                                                                ";
+
+/// The version as it appears in request and status lines, e.g. `HTTP/1.1` or `HTTP/2`
+pub fn format_version(version: Version) -> &'static str {
+    if version == Version::HTTP_09 {
+        "HTTP/0.9"
+    } else if version == Version::HTTP_10 {
+        "HTTP/1.0"
+    } else if version == Version::HTTP_2 {
+        "HTTP/2"
+    } else {
+        "HTTP/1.1"
+    }
+}
+
+/// How the `Printer` renders requests and responses
+#[derive(Clone, Copy, Debug)]
+pub struct PrintOptions {
+    pub colored_output: bool,
+    pub color_depth: ColorDepth,
+    /// The width at which highlighted lines are wrapped, if at all
+    pub wrap_width: Option<usize>,
+    pub format_options: FormatOptions,
 }
 
-pub fn print_http(header_part: String, body: Body, colored_output: bool, true_color: bool, only_body: bool) {
-    if !colored_output {
+impl Default for PrintOptions {
+    fn default() -> Self {
+        PrintOptions {
+            colored_output: false,
+            color_depth: ColorDepth::None,
+            wrap_width: None,
+            format_options: FormatOptions::default(),
+        }
+    }
+}
+
+/// Pretty prints HTTP messages. The syntax definitions and themes are loaded once and
+/// shared between clones.
+#[derive(Clone)]
+pub struct Printer {
+    pub options: PrintOptions,
+    syntax_set: Rc<SyntaxSet>,
+    theme_set: Rc<ThemeSet>,
+}
+
+impl Printer {
+    pub fn new(options: PrintOptions) -> Self {
+        Printer {
+            options: options,
+            syntax_set: Rc::new(get_syntax_set()),
+            theme_set: Rc::new(get_theme_set()),
+        }
+    }
+
+    /// Prints a request the way it is sent: the request line, headers and body
+    pub fn print_request(&self, out: &mut Write, request: &PreparedRequest) -> io::Result<()> {
+        let mut request_str = String::new();
+        let first_line = format!("{} {}{}{} {}\n", request.method.as_ref(), request.url.path(),
+                                 request.url.query().map(|_| "?").unwrap_or(""), request.url.query().unwrap_or(""),
+                                 format_version(request.version));
+        request_str.push_str(&first_line);
+        request_str.push_str(&self.options.format_options.format_headers(&request.headers));
+        self.print_http(out, request_str, request.body.clone(), false)
+    }
+
+    pub fn print_http(&self, out: &mut Write, header_part: String, body: Body, only_body: bool) -> io::Result<()> {
+        let PrintOptions { colored_output, color_depth, wrap_width, .. } = self.options;
+        if !colored_output {
+            if !only_body {
+                write_wrapped(out, &header_part, wrap_width, false)?;
+            }
+            match body {
+                Body::Empty => (),
+                Body::Form(s) => write_wrapped(out, &s, wrap_width, false)?,
+                Body::Json(s) => write_wrapped(out, &s, wrap_width, true)?,
+                Body::Binary(size, mime) => writeln!(out, "{}", binary_note(size, mime))?,
+                Body::Raw(bytes) => out.write_all(&bytes)?,
+            }
+            return Ok(())
+        }
+
+        let ss = &self.syntax_set;
+        let theme = &self.theme_set.themes["Solarized (dark)"];
+
         if !only_body {
-            println!("{}", header_part);
+            let syntax_http = ss.find_syntax_by_name("HTTP").unwrap();
+            let mut h = HighlightLines::new(syntax_http, theme);
+            for line in header_part.lines() {
+                let ranges: Vec<(Style, &str)> = h.highlight(line);
+                for wrapped in wrap_ranges(&ranges, wrap_width, 0) {
+                    let escaped = as_terminal_escaped(&wrapped[..], color_depth);
+                    writeln!(out, "{}", escaped)?;
+                }
+            }
+            writeln!(out, "\x1b[0m")?;
         }
         match body {
             Body::Empty => (),
-            Body::Form(s) => println!("{}", s),
-            Body::Json(s) => println!("{}", s),
-        }
-        return
-    }
-
-    // TODO: Find some way to get this statically
-    // Help Needed!!
-    let ts = get_theme_set();
-    let ss = get_syntax_set();
-    let theme = &ts.themes["Solarized (dark)"];
-
-    if !only_body {
-        let syntax_http = ss.find_syntax_by_name("HTTP").unwrap();
-        let mut h = HighlightLines::new(syntax_http, theme);
-        for line in header_part.lines() {
-            let ranges: Vec<(Style, &str)> = h.highlight(line);
-            let escaped = as_24_bit_terminal_escaped(&ranges[..], true_color);
-            println!("{}", escaped);
-        }
-        println!("\x1b[0m");
-    }
-    match body {
-        Body::Empty => (),
-        Body::Form(s) => println!("{}", s),
-        Body::Json(s) => {
-            let syntax_json = ss.find_syntax_by_extension("json").unwrap();
-            let mut h = HighlightLines::new(syntax_json, theme);
-            for line in s.lines() {
-                let ranges: Vec<(Style, &str)> = h.highlight(line);
-                let escaped = as_24_bit_terminal_escaped(&ranges[..], true_color);
-                println!("{}", escaped);
+            Body::Form(s) => write_wrapped(out, &s, wrap_width, false)?,
+            Body::Json(s) => {
+                let syntax_json = ss.find_syntax_by_extension("json").unwrap();
+                let mut h = HighlightLines::new(syntax_json, theme);
+                for line in s.lines() {
+                    let ranges: Vec<(Style, &str)> = h.highlight(line);
+                    // Continuation lines line up with the nesting level of the line they belong to
+                    let indent = line.len() - line.trim_left().len();
+                    for wrapped in wrap_ranges(&ranges, wrap_width, indent) {
+                        let escaped = as_terminal_escaped(&wrapped[..], color_depth);
+                        writeln!(out, "{}", escaped)?;
+                    }
+                }
+                write!(out, "\x1b[0m")?;
+            },
+            Body::Binary(size, mime) => writeln!(out, "{}", binary_note(size, mime))?,
+            Body::Raw(bytes) => out.write_all(&bytes)?,
+        }
+        Ok(())
+    }
+}
+
+pub fn get_syntax_set() -> SyntaxSet {
+    let http_def: SyntaxDefinition = SyntaxDefinition::load_from_str(
+        include_str!("./http.sublime-syntax"),
+        NEW_LINES,
+        Some("HTTP")
+    ).expect("Unable to parse http sublime syntax");
+
+    let mut ss = if NEW_LINES {
+        SyntaxSet::load_defaults_newlines()
+    } else {
+        SyntaxSet::load_defaults_nonewlines()
+    };
+    ss.add_syntax(http_def);
+    ss.link_syntaxes();
+    ss
+}
+
+pub fn get_theme_set() -> ThemeSet {
+    ThemeSet::load_defaults()
+}
+
+/// Splits a highlighted line into lines of at most `width` characters. Every piece keeps the
+/// style of the range it was cut from, so escaping each wrapped line on its own restores the
+/// colours after the break.
+fn wrap_ranges<'a>(ranges: &[(Style, &'a str)], width: Option<usize>, indent: usize) -> Vec<Vec<(Style, &'a str)>> {
+    let width = match width {
+        Some(width) if width > 0 => width,
+        _ => return vec![ranges.to_vec()],
+    };
+    // Don't bother indenting if that leaves hardly any room for the content
+    let indent = if indent > SPACES.len() || indent >= width / 2 { 0 } else { indent };
+
+    let mut lines = Vec::new();
+    let mut current = Vec::new();
+    let mut column = 0;
+    for &(style, text) in ranges {
+        let mut rest = text;
+        while !rest.is_empty() {
+            if column >= width {
+                lines.push(mem::replace(&mut current, Vec::new()));
+                if indent > 0 {
+                    current.push((style, &SPACES[..indent]));
+                }
+                column = indent;
             }
-            print!("\x1b[0m");
-        },
+            let split = rest.char_indices().nth(width - column).map(|(i, _)| i).unwrap_or(rest.len());
+            let (head, tail) = rest.split_at(split);
+            current.push((style, head));
+            column += head.chars().count();
+            rest = tail;
+        }
+    }
+    lines.push(current);
+    lines
+}
+
+/// Writes uncoloured text followed by a newline, wrapped the same way as highlighted text.
+/// Continuation lines of JSON line up with the nesting level of the line they belong to.
+fn write_wrapped(out: &mut Write, text: &str, width: Option<usize>, indent: bool) -> io::Result<()> {
+    if width.is_none() {
+        return writeln!(out, "{}", text);
+    }
+    for line in text.split('\n') {
+        let indent = if indent { line.len() - line.trim_left().len() } else { 0 };
+        for wrapped in wrap_ranges(&[(Style::default(), line)], width, indent) {
+            let wrapped = wrapped.iter().map(|&(_, text)| text).collect::<String>();
+            writeln!(out, "{}", wrapped)?;
+        }
+    }
+    Ok(())
+}
+
+fn binary_note(size: usize, mime: Option<String>) -> String {
+    format!("+-----------------------------------------+\n\
+             | NOTE: binary data not shown in terminal |\n\
+             +-----------------------------------------+\n\
+             {} bytes of {}",
+            size, mime.unwrap_or(String::from("unknown type")))
+}
+
+/// Writes already rendered output either straight to stdout or through a pager, depending
+/// on `paging_mode` and, for `QuietIfFits`, on whether it is taller than the terminal.
+pub fn write_output(output: &[u8], paging_mode: PagingMode, term_width: usize, term_height: usize) -> io::Result<()> {
+    let use_pager = match paging_mode {
+        PagingMode::Always => true,
+        PagingMode::QuietIfFits => count_lines(output, term_width) > term_height,
+        PagingMode::Never => false,
+    };
+
+    let mut output_type = if use_pager {
+        OutputType::pager()
+    } else {
+        OutputType::Stdout(io::stdout())
+    };
+    let written = output_type.handle().write_all(output).and_then(|_| output_type.handle().flush());
+    match written {
+        // The user quit the pager before reading everything
+        Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe && output_type.is_pager() => Ok(()),
+        written => written,
+    }
+}
+
+enum OutputType {
+    Pager(Child),
+    Stdout(io::Stdout),
+}
+
+impl OutputType {
+    fn pager() -> Self {
+        let pager = env::var("CHROME_PAGER")
+            .or_else(|_| env::var("PAGER"))
+            .unwrap_or(String::from("less"));
+        let (program, args) = pager_command(&pager);
+
+        let mut command = Command::new(program);
+        command
+            .args(&args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .map(OutputType::Pager)
+            .unwrap_or_else(|_| OutputType::Stdout(io::stdout()))
+    }
+
+    fn is_pager(&self) -> bool {
+        match *self {
+            OutputType::Pager(_) => true,
+            OutputType::Stdout(_) => false,
+        }
+    }
+
+    fn handle(&mut self) -> &mut Write {
+        match *self {
+            OutputType::Pager(ref mut command) => command.stdin.as_mut().expect("Could not open stdin for pager"),
+            OutputType::Stdout(ref mut handle) => handle,
+        }
+    }
+}
+
+impl Drop for OutputType {
+    fn drop(&mut self) {
+        if let OutputType::Pager(ref mut command) = *self {
+            // Closes the pager's stdin and waits for the user to quit it
+            let _ = command.wait();
+        }
+    }
+}
+
+/// Splits the pager command into the program and its arguments. `less` is always told to keep
+/// colours, quit if the output fits anyway and not clear the screen, before any options of
+/// the user's own, which take precedence.
+fn pager_command(pager: &str) -> (&str, Vec<&str>) {
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    let mut args = Vec::new();
+    if Path::new(program).file_name().map(|name| name == "less").unwrap_or(false) {
+        args.extend(&["-R", "-F", "-X"]);
+    }
+    args.extend(words);
+    (program, args)
+}
+
+/// Counts the lines `output` takes up on screen, including those caused by wrapping.
+fn count_lines(output: &[u8], term_width: usize) -> usize {
+    String::from_utf8_lossy(output)
+        .lines()
+        .map(|line| {
+            let width = visible_width(line);
+            if term_width == 0 || width == 0 { 1 } else { (width + term_width - 1) / term_width }
+        })
+        .sum()
+}
+
+/// The width of `line` on screen, skipping over ansi escape sequences.
+fn visible_width(line: &str) -> usize {
+    let mut width = 0;
+    let mut chars = line.chars();
+    while let Some(chr) = chars.next() {
+        if chr == '\x1b' {
+            // Skip until the end of the escape sequence, which is always a letter
+            while let Some(c) = chars.next() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(text: &str, width: Option<usize>, indent: bool) -> String {
+        let mut out = Vec::new();
+        write_wrapped(&mut out, text, width, indent).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn plain_text_is_wrapped_at_the_width() {
+        assert_eq!(wrapped("abcdefgh\nij", Some(3), false), "abc\ndef\ngh\nij\n");
+        assert_eq!(wrapped("abcdefgh\n", Some(4), false), "abcd\nefgh\n\n");
+    }
+
+    #[test]
+    fn plain_json_continues_at_its_indent() {
+        assert_eq!(wrapped("{\n    \"key\": 1\n}", Some(10), true), "{\n    \"key\":\n     1\n}\n");
+    }
+
+    #[test]
+    fn plain_text_is_left_alone_without_a_width() {
+        assert_eq!(wrapped("abcdefgh\n", None, false), "abcdefgh\n\n");
+    }
+
+    #[test]
+    fn less_always_keeps_colours() {
+        assert_eq!(pager_command("less"), ("less", vec!["-R", "-F", "-X"]));
+        assert_eq!(pager_command("/usr/bin/less -i"), ("/usr/bin/less", vec!["-R", "-F", "-X", "-i"]));
+        assert_eq!(pager_command("most -s"), ("most", vec!["-s"]));
     }
 }
@@ -1,19 +1,35 @@
-use actix_web::client::{ ClientRequest, ClientRequestBuilder, ClientResponse };
+use actix_web::client::{ ClientRequest, Connection };
+use actix_web::server::IoStream;
+use actix_web::http::{ Method, Version };
+use actix_web::http::header::{ self, HeaderMap, HeaderName, HeaderValue };
+use bytes::Bytes;
 use futures::future::{ self, Future };
 use serde_json::{self, Value};
 use serde_urlencoded;
 use url::Url;
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::collections::HashSet;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+use std::time::Instant;
 
-use cli::Config;
+use cassette::{CassetteMode, Cassettes};
+use connection::{HttpVersion, Stream, connect, split_unix_url};
 use errors::ChromeError;
+use files::{expand_path, read_file, read_to_string, read_value};
+use format::{FormatOptions, json_object};
+use http2;
+use request_item::{ItemParseError, RequestItem, get_request_item};
 use request_item::RequestItemType::*;
 use output::*;
+use response::Response;
+use retry::{Attempt, RetryPolicy, with_retries};
+use schema::Schema;
+use timing::Timing;
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy)]
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy, Debug)]
 pub enum BodyType {
     JSON,
     Form,
@@ -21,115 +37,600 @@ pub enum BodyType {
     Undecided,
 }
 
-pub fn make_request(config: &Config) -> Box<Future<Item = ClientResponse, Error = ChromeError>> {
-    let mut req_builder = ClientRequest::build();
-    req_builder
-        .header("User-Agent", format!("{}/{}", crate_name!(), crate_version!()))
-        .method(config.method.clone());
+/// Everything needed to build a request, independent of the command line:
+///
+/// ```
+/// # extern crate actix_web;
+/// # extern crate chrome;
+/// # use actix_web::http::Method;
+/// # use chrome::format::FormatOptions;
+/// # use chrome::request::RequestSpec;
+/// # fn main() {
+/// let spec = RequestSpec::new(Method::POST, "https://example.org/users")
+///     .item("name=chrome").unwrap()
+///     .item("admin:=true").unwrap()
+///     .item("q==search").unwrap();
+/// let prepared = spec.prepare(&FormatOptions::default()).unwrap();
+/// assert_eq!(prepared.url.as_str(), "https://example.org/users?q=search");
+/// assert_eq!(&prepared.payload[..], &b"{\"name\":\"chrome\",\"admin\":true}"[..]);
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct RequestSpec {
+    pub method: Method,
+    pub url: String,
+    pub items: Vec<RequestItem>,
+    pub body_type: BodyType,
+    /// Sent as the whole body instead of one built from items
+    pub raw_body: Option<String>,
+    /// Connect to this socket instead of the host of the url
+    pub unix_socket: Option<PathBuf>,
+    pub http_version: HttpVersion,
+    pub retry: RetryPolicy,
+    /// Open the connection ourselves, so that each phase of setting it up can be timed
+    pub timed: bool,
+}
+
+impl RequestSpec {
+    /// A `http+unix://` url is split into the socket and the url of the request
+    pub fn new<U: AsRef<str>>(method: Method, url: U) -> Self {
+        let (unix_socket, url) = match split_unix_url(url.as_ref()) {
+            Some((socket, url)) => (Some(socket), url),
+            None => (None, String::from(url.as_ref())),
+        };
+        RequestSpec {
+            method: method,
+            url: url,
+            items: Vec::new(),
+            body_type: BodyType::Undecided,
+            raw_body: None,
+            unix_socket: unix_socket,
+            http_version: HttpVersion::Http11,
+            retry: RetryPolicy::default(),
+            timed: false,
+        }
+    }
+
+    /// Adds an item in the syntax of the command line, e.g. `name=chrome` or `Accept:text/html`
+    pub fn item(mut self, item: &str) -> Result<Self, ItemParseError> {
+        self.items.push(get_request_item(String::from(item))?);
+        Ok(self)
+    }
+
+    pub fn items<I: IntoIterator<Item = RequestItem>>(mut self, items: I) -> Self {
+        self.items.extend(items);
+        self
+    }
+
+    pub fn body_type(mut self, body_type: BodyType) -> Self {
+        self.body_type = body_type;
+        self
+    }
+
+    pub fn raw_body<S: Into<String>>(mut self, body: S) -> Self {
+        self.raw_body = Some(body.into());
+        self
+    }
+
+    pub fn unix_socket<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.unix_socket = Some(path.into());
+        self
+    }
+
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    pub fn timed(mut self, timed: bool) -> Self {
+        self.timed = timed;
+        self
+    }
+
+    /// Builds the headers and body from the items. `format_options` only affect how a JSON
+    /// body is displayed, not how it is sent.
+    pub fn prepare(&self, format_options: &FormatOptions) -> Result<PreparedRequest, ChromeError> {
+        parse_request_items(self, format_options)
+    }
+}
+
+/// A request built from a `RequestSpec`, which doesn't depend on a connection yet.
+#[derive(Clone)]
+pub struct PreparedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub version: Version,
+    pub headers: HeaderMap,
+    /// The body as it is displayed
+    pub body: Body,
+    /// The body as it is sent
+    pub payload: Bytes,
+    /// Connect to this socket instead of the host of the url
+    pub unix_socket: Option<PathBuf>,
+    pub http_version: HttpVersion,
+    pub timed: bool,
+}
+
+impl PreparedRequest {
+    /// Sends the request, retrying it as far as `policy` allows
+    pub fn send(self, policy: &RetryPolicy) -> Attempt {
+        if !policy.applies_to(&self.method) {
+            return send_request(self);
+        }
+        // Every attempt sends the same request again, body included
+        with_retries(policy.clone(), move || send_request(self.clone()))
+    }
+
+    /// Builds the request for actix's connection pool, which keeps connections alive for the
+    /// requests after it. The pool only speaks HTTP/1.1 over TCP, so requests for a unix
+    /// socket or HTTP/2 are turned down rather than sent some other way.
+    pub fn pooled(self) -> Result<ClientRequest, ChromeError> {
+        if self.unix_socket.is_some() || self.http_version != HttpVersion::Http11 {
+            return Err(ChromeError::ConnectionError {
+                message: String::from("Only HTTP/1.1 requests over TCP can use the connection pool"),
+            });
+        }
+        self.build(None)
+    }
+
+    /// Whether the request can't be left to actix's connector, which only speaks HTTP/1.1
+    /// over TCP and doesn't tell how long each phase took
+    fn needs_own_connection(&self) -> bool {
+        self.timed || self.unix_socket.is_some() || self.http_version != HttpVersion::Http11
+    }
+
+    fn build(self, connection: Option<Connection>) -> Result<ClientRequest, ChromeError> {
+        let mut req = ClientRequest::build();
+        req.method(self.method)
+            .uri(self.url.as_str())
+            .version(self.version)
+            // Our own defaults are already in the headers, unless they were unset
+            .no_default_headers();
+        if let Some(connection) = connection {
+            req.with_connection(connection);
+        }
+        for (key, value) in self.headers.iter() {
+            req.header(key.clone(), value.clone());
+        }
+        let request = match self.body {
+            Body::Empty => req.finish(),
+            _ => req.body(self.payload),
+        };
+        request.map_err(ChromeError::from)
+    }
+}
+
+/// What happens around sending a request in `make_request`
+#[derive(Clone, Default)]
+pub struct RequestOptions {
+    /// Print the request before it is sent
+    pub verbose: bool,
+    pub cassettes: Option<Cassettes>,
+    /// Checked against the body before anything is sent
+    pub schema: Option<Rc<Schema>>,
+}
+
+/// Prepares the request and sends it, or answers it from a cassette
+pub fn make_request(spec: &RequestSpec, printer: &Printer, options: &RequestOptions) -> Attempt {
+    let prepared = match spec.prepare(&printer.options.format_options) {
+        Err(e) => return Box::new(future::err(e)),
+        Ok(prepared) => prepared,
+    };
+    // With `--http2` over https the version is only known once the TLS handshake settled
+    // on it, so the request is printed along with the version that was actually used
+    let negotiated = spec.http_version == HttpVersion::Http2 && prepared.version == Version::HTTP_2;
+    let echo = if options.verbose && negotiated { Some((printer.clone(), prepared.clone())) } else { None };
+    if options.verbose && !negotiated {
+        if let Err(e) = process_request(printer, &prepared) {
+            return Box::new(future::err(e));
+        }
+    }
 
-    match parse_request_items(config, req_builder) {
+    let attempt: Attempt = match check_request_schema(printer, options, &prepared) {
         Err(e) => Box::new(future::err(e)),
-        Ok((body, request)) => {
-            if config.verbose {
-                process_request(config, &request, body);
+        Ok(()) => match options.cassettes {
+            Some(ref cassettes) if cassettes.mode == CassetteMode::Replay => cassettes.replay(&prepared),
+            Some(ref cassettes) => cassettes.record(prepared, &spec.retry),
+            None => prepared.send(&spec.retry),
+        },
+    };
+    match echo {
+        None => attempt,
+        Some((printer, mut request)) => Box::new(attempt.then(move |result| {
+            if let Ok((ref response, _)) = result {
+                request.version = response.version;
             }
-            Box::new(request.send().map_err(ChromeError::from))
-        }
+            process_request(&printer, &request)?;
+            result
+        })),
+    }
+}
+
+fn check_request_schema(printer: &Printer, options: &RequestOptions, prepared: &PreparedRequest) -> Result<(), ChromeError> {
+    match options.schema {
+        Some(ref schema) => {
+            let body = serde_json::from_slice::<Value>(&prepared.payload).ok();
+            let print_options = &printer.options;
+            schema.check_body("request body", body.as_ref(), &print_options.format_options, print_options.colored_output)
+        },
+        None => Ok(()),
     }
 }
 
-fn process_request(config: &Config, request: &ClientRequest, body: Body) {
-    let mut request_str = String::new();
-    let first_line = format!("{} {}{}{} {:?}\n", request.method().as_ref(), request.uri().path(),
-                             request.uri().query().map(|_| "?").unwrap_or(""), request.uri().query().unwrap_or(""),
-                             request.version());
-    request_str.push_str(&first_line);
-    for (key, value) in request.headers().iter() {
-        let headerval_pair = format!("{}: {}\n", key.as_str(), value.to_str().expect(""));
-        request_str.push_str(&headerval_pair);
+fn send_request(prepared: PreparedRequest) -> Attempt {
+    if !prepared.needs_own_connection() {
+        return send_pooled(prepared);
+    }
+    let version = prepared.http_version;
+    Box::new(connect(&prepared.url, prepared.unix_socket.as_ref().map(PathBuf::as_path), version)
+        .and_then(move |(stream, connect_timing)| {
+            let sent = Instant::now();
+            let h2 = version == HttpVersion::Http2PriorKnowledge || stream.negotiated_h2();
+            let response = match stream {
+                Stream::Tcp(stream) => send_over(stream, prepared, h2),
+                Stream::Tls(stream) => send_over(stream, prepared, h2),
+                #[cfg(unix)]
+                Stream::Unix(stream) => send_over(stream, prepared, h2),
+            };
+            response.map(move |response| {
+                let timing = Timing {
+                    dns: connect_timing.dns,
+                    connect: connect_timing.connect,
+                    tls: connect_timing.tls,
+                    ttfb: sent.elapsed(),
+                    download: Default::default(),
+                };
+                (response, timing)
+            })
+        }))
+}
+
+/// Leaves the connection to actix's connector, which resolves names without blocking, tries
+/// every address and keeps connections alive. Only the time to the first byte is known.
+fn send_pooled(prepared: PreparedRequest) -> Attempt {
+    let sent = Instant::now();
+    match prepared.pooled() {
+        Ok(request) => Box::new(request.send().map_err(ChromeError::from).map(move |response| {
+            (Response::from(response), Timing { ttfb: sent.elapsed(), ..Timing::default() })
+        })),
+        Err(e) => Box::new(future::err(e)),
+    }
+}
+
+/// Speaks HTTP/2 over `stream` if it was negotiated or asked for, HTTP/1.x through actix otherwise
+fn send_over<T: IoStream + Send>(stream: T, mut prepared: PreparedRequest, h2: bool) -> Box<Future<Item = Response, Error = ChromeError>> {
+    if h2 {
+        return http2::send(stream, prepared);
+    }
+    // The server didn't agree to HTTP/2 during ALPN
+    prepared.version = Version::HTTP_11;
+    match prepared.build(Some(Connection::from_stream(stream))) {
+        Ok(request) => Box::new(request.send().map_err(ChromeError::from).map(Response::from)),
+        Err(e) => Box::new(future::err(e)),
     }
-    print_http(request_str, body, config.colored_output, config.true_color, false);
-    println!("");
 }
 
-fn parse_request_items(config: &Config, mut req: ClientRequestBuilder) -> Result<(Body, ClientRequest), ChromeError> {
+fn process_request(printer: &Printer, request: &PreparedRequest) -> Result<(), ChromeError> {
+    // The request is printed right away rather than paged along with the response
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    printer.print_request(&mut out, request)?;
+    writeln!(out, "")?;
+    Ok(())
+}
 
-    // Process query params
-    let query_params: Vec<(&String, &String)> = config.items.iter()
-        .filter(|x| match x.variant { URLParameter => true, _ => false })
-        .map(|x| (&x.key, &x.value))
+/// The body given with `--raw` or a bare `@file` item, along with the content type to send
+/// it with unless a header item sets one
+fn raw_body(spec: &RequestSpec) -> Result<Option<(Vec<u8>, Option<&'static str>)>, ChromeError> {
+    let files: Vec<&str> = spec.items.iter()
+        .filter(|x| match x.variant { BodyFile => true, _ => false })
+        .map(|x| x.value.as_str())
         .collect();
-    let url = Url::parse_with_params(&config.url, &query_params)?;
+    let sources = files.len() + if spec.raw_body.is_some() { 1 } else { 0 };
+    if sources == 0 {
+        return Ok(None);
+    }
+    if sources > 1 {
+        return Err(ChromeError::BodyError {
+            message: String::from("Only one of --raw and @file can be used as the body"),
+        });
+    }
+    let has_body_items = spec.items.iter().any(|x| match x.variant {
+        HTTPHeader | EmptyHTTPHeader | FileHTTPHeader | URLParameter | FileURLParameter | BodyFile => false,
+        _ => true,
+    });
+    if has_body_items {
+        return Err(ChromeError::BodyError {
+            message: String::from("--raw and @file can't be combined with data, JSON or file request items"),
+        });
+    }
+
+    if let Some(ref raw) = spec.raw_body {
+        let content_type = match spec.body_type {
+            BodyType::JSON => Some("application/json"),
+            BodyType::Form => Some("application/x-www-form-urlencoded"),
+            _ => None,
+        };
+        return Ok(Some((raw.clone().into_bytes(), content_type)));
+    }
+    let contents = read_file(files[0])?;
+    Ok(Some((contents, guess_content_type(&expand_path(files[0])))))
+}
+
+/// Files without an extension are sent without a Content-Type
+fn guess_content_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let content_type = match extension.as_str() {
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "graphql" | "gql" => "application/graphql",
+        "html" | "htm" => "text/html",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "yaml" | "yml" => "application/x-yaml",
+        "js" => "application/javascript",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        _ => "application/octet-stream",
+    };
+    Some(content_type)
+}
+
+/// Raw bodies are echoed as they are sent, only highlighted when they are JSON
+fn raw_display(payload: &[u8], content_type: Option<&str>) -> Body {
+    let is_json = content_type.map(|t| t.contains("json")).unwrap_or(false);
+    match str::from_utf8(payload) {
+        Ok(text) if is_json => Body::Json(String::from(text)),
+        Ok(text) => Body::Form(String::from(text)),
+        Err(_) => Body::Binary(payload.len(), content_type.map(String::from)),
+    }
+}
+
+/// Appends URL parameter items to the url in order, repeated keys included
+fn url_with_params(url: &str, items: &[RequestItem]) -> Result<Url, ChromeError> {
+    let mut query_params: Vec<(&str, String)> = Vec::new();
+    for item in items {
+        match item.variant {
+            URLParameter => query_params.push((item.key.as_str(), item.value.clone())),
+            FileURLParameter => query_params.push((item.key.as_str(), read_value(&item.value)?)),
+            _ => (),
+        }
+    }
+    Ok(Url::parse_with_params(url, &query_params)?)
+}
+
+/// Form bodies can repeat keys as they are, `tag=a&tag=b`
+fn form_pairs(fields: Vec<(String, Value)>) -> Vec<(String, String)> {
+    fields.into_iter()
+        .map(|(key, value)| match value {
+            Value::String(s) => (key, s),
+            other => (key, other.to_string()),
+        })
+        .collect()
+}
 
-    req.uri(url.as_str());
+/// Sets a header unless the user already did, or unset it with `Header:`
+fn set_default_header(headers: &mut HeaderMap, unset: &HashSet<HeaderName>, name: HeaderName, value: &str)
+    -> Result<(), ChromeError> {
+    if !headers.contains_key(&name) && !unset.contains(&name) {
+        headers.insert(name, HeaderValue::from_str(value)?);
+    }
+    Ok(())
+}
+
+fn multipart_unsupported() -> ChromeError {
+    ChromeError::BodyError { message: String::from("Multipart file uploads aren't supported yet") }
+}
+
+fn parse_request_items(spec: &RequestSpec, format_options: &FormatOptions) -> Result<PreparedRequest, ChromeError> {
+
+    let url = url_with_params(&spec.url, &spec.items)?;
 
    // Process headers
-    let headers = config.items.iter()
-        .filter(|x| match x.variant { HTTPHeader => true, _ => false });
+    let header_items = spec.items.iter()
+        .filter(|x| match x.variant { HTTPHeader | EmptyHTTPHeader | FileHTTPHeader => true, _ => false });
 
-    for header in headers {
-        req.header(header.key.as_str(), header.value.as_str());
+    // Repeated headers are all sent. `Header:` without a value unsets the header, including
+    // any default for it, while `Header;` sends it with an empty value.
+    let mut headers = HeaderMap::new();
+    let mut unset = HashSet::new();
+    for header in header_items {
+        let name = HeaderName::from_bytes(header.key.as_bytes())?;
+        match header.variant {
+            HTTPHeader if header.value.is_empty() => {
+                headers.remove(&name);
+                unset.insert(name);
+            },
+            FileHTTPHeader => {
+                unset.remove(&name);
+                headers.append(name, HeaderValue::from_str(&read_value(&header.value)?)?);
+            },
+            _ => {
+                unset.remove(&name);
+                headers.append(name, HeaderValue::from_str(header.value.as_str())?);
+            },
+        }
+    }
+    // The same defaults actix would add, so that they show up in verbose output
+    let mut defaults = vec![
+        (header::USER_AGENT, format!("{}/{}", crate_name!(), crate_version!())),
+        (header::ACCEPT_ENCODING, String::from("gzip, deflate")),
+    ];
+    if let Some(host) = url.host_str() {
+        let host = url.port().map(|port| format!("{}:{}", host, port)).unwrap_or(String::from(host));
+        defaults.push((header::HOST, host));
+    }
+    for (name, value) in defaults {
+        set_default_header(&mut headers, &unset, name, &value)?;
     }
 
     // Process body
-    let body_items = config.items.iter()
-        .filter(|x| match x.variant { HTTPHeader => false, URLParameter => false, _ => true });
+    let raw = raw_body(spec)?;
+    let body_items = spec.items.iter()
+        .filter(|x| match x.variant {
+            HTTPHeader | EmptyHTTPHeader | FileHTTPHeader | URLParameter | FileURLParameter | BodyFile => false,
+            _ => true,
+        });
 
-    // TODO: Allow overriding default in config
-    let mut body_type = config.body_type;
-    let mut json_map: HashMap<String, Value> = HashMap::new();
-    let mut data_map: HashMap<String, String> = HashMap::new();
+    // Fields are kept in the order they were given in, so that the body is deterministic
+    let mut body_type = spec.body_type;
+    let mut fields: Vec<(String, Value)> = Vec::new();
     for item in body_items {
         match item.variant {
             DataField => {
-                data_map.insert(item.key.clone(), item.value.clone());
+                fields.push((item.key.clone(), Value::String(item.value.clone())));
             },
             JsonData => {
                 if body_type != BodyType::Undecided && body_type != BodyType::JSON {
                     return Err(ChromeError::UnexpectedError);
                 }
                 body_type = BodyType::JSON;
-                json_map.insert(item.key.clone(), serde_json::from_str(item.value.as_str())?);
+                fields.push((item.key.clone(), serde_json::from_str(item.value.as_str())?));
             },
             FileDataField => {
-                let mut file = File::open(item.value.as_str())?;
-                let mut contents = String::new();
-                file.read_to_string(&mut contents)?;
-                data_map.insert(item.key.clone(), contents);
+                fields.push((item.key.clone(), Value::String(read_to_string(&item.value)?)));
             },
             FileJsonData => {
                 if body_type != BodyType::Undecided && body_type != BodyType::JSON {
                     return Err(ChromeError::UnexpectedError);
                 }
                 body_type = BodyType::JSON;
-                let mut file = File::open(item.value.as_str())?;
-                json_map.insert(item.key.clone(), serde_json::from_reader(file)?);
+                fields.push((item.key.clone(), serde_json::from_slice(&read_file(&item.value)?)?));
             }
             FormFile => {
                 if body_type == BodyType::JSON {
                     return Err(ChromeError::UnexpectedError);
                 }
-                // body_type = BodyType::Multipart;
-                unimplemented!()
+                return Err(multipart_unsupported());
             },
             _ => return Err(ChromeError::UnexpectedError),
         };
     }
-    match body_type {
-        BodyType::Undecided => {
-            if data_map.is_empty() {
-                Ok((Body::Empty, req.finish()?))
-            } else {
-                Ok((Body::Json(serde_json::to_string_pretty(&data_map)?), req.json(data_map)?))
-            }
-        },
-        BodyType::JSON => {
-            json_map.extend(data_map.into_iter().map(|(k, v)| (k, Value::String(v))));
-            Ok((Body::Json(serde_json::to_string_pretty(&json_map)?), req.json(json_map)?))
-        },
-        BodyType::Form => {
-            Ok((Body::Form(serde_urlencoded::to_string(&data_map)?), req.form(data_map)?))
-        },
-        BodyType::Multipart => unimplemented!(),
+    let (body, payload) = if let Some((payload, content_type)) = raw {
+        if let Some(content_type) = content_type {
+            set_default_header(&mut headers, &unset, header::CONTENT_TYPE, content_type)?;
+        }
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+        (raw_display(&payload, content_type), Bytes::from(payload))
+    } else {
+        match body_type {
+            BodyType::Undecided if fields.is_empty() => (Body::Empty, Bytes::new()),
+            BodyType::Undecided | BodyType::JSON => {
+                let object = json_object(fields);
+                set_default_header(&mut headers, &unset, header::CONTENT_TYPE, "application/json")?;
+                (Body::Json(format_options.format_json(&object)?), Bytes::from(serde_json::to_vec(&object)?))
+            },
+            BodyType::Form => {
+                let form = serde_urlencoded::to_string(&form_pairs(fields))?;
+                set_default_header(&mut headers, &unset, header::CONTENT_TYPE, "application/x-www-form-urlencoded")?;
+                (Body::Form(form.clone()), Bytes::from(form))
+            },
+            BodyType::Multipart => return Err(multipart_unsupported()),
+        }
+    };
+
+    // Without prior knowledge HTTP/2 can only be negotiated over TLS, so for plain http
+    // urls `--http2` falls back to HTTP/1.1
+    let version = match spec.http_version {
+        HttpVersion::Http2PriorKnowledge => Version::HTTP_2,
+        HttpVersion::Http2 if url.scheme() == "https" && spec.unix_socket.is_none() => Version::HTTP_2,
+        _ => Version::HTTP_11,
+    };
+
+    Ok(PreparedRequest {
+        method: spec.method.clone(),
+        url: url,
+        version: version,
+        headers: headers,
+        body: body,
+        payload: payload,
+        unix_socket: spec.unix_socket.clone(),
+        http_version: spec.http_version,
+        timed: spec.timed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use request_item::get_request_item;
+
+    fn fields(pairs: &[(&str, Value)]) -> Vec<(String, Value)> {
+        pairs.iter().map(|&(key, ref value)| (String::from(key), value.clone())).collect()
+    }
+
+    #[test]
+    fn json_keeps_the_order_fields_were_given_in() {
+        let object = json_object(fields(&[
+            ("zebra", Value::from("z")), ("apple", Value::from(1)), ("mango", Value::from(true)),
+        ]));
+        let keys: Vec<&str> = object.keys().map(String::as_str).collect();
+        assert_eq!(keys, vec!["zebra", "apple", "mango"]);
+    }
+
+    #[test]
+    fn json_repeated_keys_become_arrays() {
+        let object = json_object(fields(&[
+            ("tag", Value::from("a")), ("id", Value::from(1)), ("tag", Value::from("b")), ("tag", Value::from("c")),
+        ]));
+        assert_eq!(serde_json::to_string(&object).unwrap(), r#"{"tag":["a","b","c"],"id":1}"#);
+    }
+
+    #[test]
+    fn json_repeated_array_values_are_nested() {
+        let object = json_object(fields(&[
+            ("list", serde_json::from_str("[1, 2]").unwrap()), ("list", Value::from(3)),
+        ]));
+        assert_eq!(serde_json::to_string(&object).unwrap(), r#"{"list":[[1,2],3]}"#);
+    }
+
+    #[test]
+    fn form_repeated_keys_become_repeated_pairs() {
+        let pairs = form_pairs(fields(&[
+            ("tag", Value::from("a")), ("name", Value::from("chrome")), ("tag", Value::from("b")),
+        ]));
+        assert_eq!(serde_urlencoded::to_string(&pairs).unwrap(), "tag=a&name=chrome&tag=b");
+    }
+
+    #[test]
+    fn url_parameters_keep_repeated_keys_in_order() {
+        let items: Vec<RequestItem> = ["q==rust", "page==2", "q==http"].iter()
+            .map(|item| get_request_item(String::from(*item)).unwrap())
+            .collect();
+        let url = url_with_params("http://example.org/search", &items).unwrap();
+        assert_eq!(url.query(), Some("q=rust&page=2&q=http"));
+    }
+
+    #[test]
+    fn unset_content_type_stays_unset_for_json_bodies() {
+        let items = ["Content-Type:", "name=chrome"].iter()
+            .map(|item| get_request_item(String::from(*item)).unwrap());
+        let prepared = RequestSpec::new(Method::POST, "http://example.org/")
+            .items(items)
+            .prepare(&FormatOptions::default())
+            .unwrap();
+        assert!(prepared.headers.get(header::CONTENT_TYPE).is_none());
+        assert_eq!(&prepared.payload[..], &br#"{"name":"chrome"}"#[..]);
+    }
+
+    #[test]
+    fn file_uploads_are_an_error() {
+        let result = RequestSpec::new(Method::POST, "http://example.org/")
+            .item("cv@./cv.pdf")
+            .unwrap()
+            .prepare(&FormatOptions::default());
+        match result {
+            Err(ChromeError::BodyError { message }) => assert_eq!(message, "Multipart file uploads aren't supported yet"),
+            _ => panic!("expected a body error"),
+        }
     }
 }
@@ -1,40 +1,56 @@
+use serde_json::{self, Value};
+
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::error;
+use std::fmt;
 use std::sync::Mutex;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RequestItemType {
+    /// A header, or with an empty value, a header which shouldn't be sent at all
     HTTPHeader,
+    /// A header which is sent with an empty value
+    EmptyHTTPHeader,
+    /// A header whose value is read from a file
+    FileHTTPHeader,
     URLParameter,
+    /// A URL parameter whose value is read from a file
+    FileURLParameter,
     DataField,
     JsonData,
     FormFile,
     FileDataField,
     FileJsonData,
+    /// A bare `@path/to/file` whose content is sent as the whole body
+    BodyFile,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct RequestItem {
     pub key: String,
     pub value: String,
     pub variant: RequestItemType,
 }
 
+/// Parts of an item, along with the byte offset in the item they start at
 enum TokenisedItem {
-    Normal (String),
-    Escaped (String),
+    Normal (String, usize),
+    Escaped (String, usize),
 }
 
 lazy_static! {
-    // NOTE: This should be descending order of len of separators
-    // TODO: Use type of SEPARATORS as BTreeSet once rfind is no longer in nightly to avoid this
-    static ref SEPARATORS: Vec<&'static str> = vec![":=@", ":=", "=@", "==", "@", "=", ":"];
+    // The leftmost separator in an item wins, and the longest one where several start at the
+    // same place, so that `pass=a:@b` is a data field and `Cookie:a=b;c=d` a header
+    static ref SEPARATORS: Vec<&'static str> = vec![":=@", "==@", ":=", "=@", ":@", "==", "@", "=", ":", ";"];
 
     static ref SEPARATOR_MAP: HashMap<&'static str, RequestItemType> = SEPARATORS.iter().cloned()
         .zip(
-            [ RequestItemType::FileJsonData, RequestItemType::JsonData, RequestItemType::FileDataField,
-              RequestItemType::URLParameter, RequestItemType::FormFile, RequestItemType::DataField,
-              RequestItemType::HTTPHeader
+            [ RequestItemType::FileJsonData, RequestItemType::FileURLParameter, RequestItemType::JsonData,
+              RequestItemType::FileDataField, RequestItemType::FileHTTPHeader, RequestItemType::URLParameter,
+              RequestItemType::FormFile, RequestItemType::DataField, RequestItemType::HTTPHeader,
+              RequestItemType::EmptyHTTPHeader
             ].iter().cloned()
         )
         .collect();
@@ -51,89 +67,280 @@ lazy_static! {
     static ref CACHE: Mutex<HashMap<String, RequestItem>> = Mutex::new(HashMap::new());
 }
 
+/// Why an item couldn't be parsed. `offset` is the byte in `item` where things went wrong.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemParseError {
+    pub item: String,
+    pub offset: usize,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for ItemParseError {
+    /// Shows the item with a caret under the offending byte, e.g.
+    ///
+    /// ```text
+    /// `;` sends a header with an empty value, so nothing may follow it
+    ///   X-Empty;value
+    ///           ^
+    ///   help: use `X-Empty:value` to send a header with a value
+    /// ```
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut offset = self.offset.min(self.item.len());
+        while !self.item.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let column = self.item[..offset].chars().count();
+        writeln!(f, "{}", self.message)?;
+        writeln!(f, "  {}", self.item)?;
+        write!(f, "  {}^", " ".repeat(column))?;
+        if let Some(ref suggestion) = self.suggestion {
+            write!(f, "\n  help: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ItemParseError {
+    fn description(&self) -> &str {
+        &self.message
+    }
+}
+
+/// How an item was split up, as shown by `chrome explain`
+#[derive(Clone, Debug)]
+pub struct ParsedItem {
+    pub item: RequestItem,
+    pub separator: &'static str,
+    pub separator_offset: usize,
+    /// Characters which were escaped with a backslash, with the offset of the backslash
+    pub escaped: Vec<(usize, char)>,
+}
+
 pub fn is_request_item(v: String) -> Result<(), String> {
-    get_request_item(v).map(|_| ())
-        .ok_or(String::from("The value does not match the format of a RequestItem"))
+    parse_request_item(&v).map(|_| ()).map_err(|e| e.to_string())
 }
 
-pub fn get_request_item(v: String) -> Option<RequestItem> {
+pub fn get_request_item(v: String) -> Result<RequestItem, ItemParseError> {
     if let Some(item) = get_from_cache(&v) {
-        return Some(item);
+        return Ok(item);
+    };
+    let parsed = parse_request_item(&v)?;
+    insert_to_cache(v, parsed.item.clone());
+    Ok(parsed.item)
+}
+
+/// A key or value along with the offset in the original item of each of its bytes, so
+/// that errors can point at the right place even when there were escapes before it
+#[derive(Default)]
+struct Part {
+    text: String,
+    offsets: Vec<usize>,
+}
+
+impl Part {
+    fn push(&mut self, text: &str, offset: usize) {
+        self.text.push_str(text);
+        self.offsets.extend(offset..offset + text.len());
+    }
+
+    fn offset_of(&self, index: usize, end: usize) -> usize {
+        self.offsets.get(index).cloned().unwrap_or(end)
+    }
+}
+
+pub fn parse_request_item(v: &str) -> Result<ParsedItem, ItemParseError> {
+    let error = |offset: usize, message: String, suggestion: Option<String>| ItemParseError {
+        item: String::from(v),
+        offset: offset,
+        message: message,
+        suggestion: suggestion,
     };
 
+    // A leading `@` can't start a key, so `@data.json` is a body file rather than a
+    // form file without a name
+    if v.starts_with('@') {
+        if v.len() == 1 {
+            return Err(error(1, String::from("Missing a file path after `@`"),
+                             Some(String::from("use `@path/to/file` to send a file as the body"))));
+        }
+        return Ok(ParsedItem {
+            item: RequestItem {
+                variant: RequestItemType::BodyFile,
+                key: String::new(),
+                value: String::from(&v[1..]),
+            },
+            separator: "@",
+            separator_offset: 0,
+            escaped: Vec::new(),
+        });
+    }
+
     // Better tokenization needed. Maybe use [1] once its
     // mature enough.
     // [1] - https://github.com/Jeffail/tokesies
-    let tokens = tokenize(&v);
+    let tokens = tokenize(v);
 
-    let mut separator = None;
-    let mut key = String::new();
-    let mut value = String::new();
+    let mut separator: Option<(&'static str, usize)> = None;
+    let mut key = Part::default();
+    let mut value = Part::default();
+    let mut escaped = Vec::new();
     for token in tokens {
-        if let TokenisedItem::Normal(tok) = token {
-
-            // If separator is None, search for it in this token
-            if let None = separator {
-                separator = SEPARATORS.iter().find(|&sep| tok.contains(sep));
-
-                // If found, append first half to key and others to value as is
-                if let Some(sep) = separator {
-                    let mut splitted = tok.split(sep);
-                    if let Some(x) = splitted.next() {
-                        key.push_str(x);
-                    } else {
-                        return None;
+        match token {
+            TokenisedItem::Normal(tok, offset) => {
+                // If separator is None, search for it in this token
+                if separator.is_none() {
+                    let found = SEPARATORS.iter()
+                        .filter_map(|&sep| tok.find(sep).map(|index| (sep, index)))
+                        .min_by_key(|&(sep, index)| (index, Reverse(sep.len())));
+
+                    // If found, append first half to key and others to value as is
+                    if let Some((sep, index)) = found {
+                        separator = Some((sep, offset + index));
+                        key.push(&tok[..index], offset);
+                        value.push(&tok[index + sep.len()..], offset + index + sep.len());
+                        continue
                     }
-                    let remaining: Vec<&str> = splitted.collect();
-                    value.push_str(remaining.join(sep).as_str());
-                    continue
                 }
-            }
 
-            // If separator not found yet, this is part of key
-            if let None = separator {
-                key.push_str(tok.as_str());
-            } else {
-                value.push_str(tok.as_str());
+                // If separator not found yet, this is part of key
+                if separator.is_none() {
+                    key.push(&tok, offset);
+                } else {
+                    value.push(&tok, offset);
+                }
+            },
+            TokenisedItem::Escaped(tok, offset) => {
+                escaped.push((offset, tok.chars().next().unwrap()));
+                // If separator not found yet, this is part of key
+                if separator.is_none() {
+                    key.push(&tok, offset + 1);
+                } else {
+                    value.push(&tok, offset + 1);
+                }
+            },
+        }
+    }
+
+    let (separator, separator_offset) = match separator {
+        Some(separator) => separator,
+        None => return Err(error(v.len(), format!("No separator found in `{}`", v), Some(String::from(
+            "use `name:value` for a header, `name==value` for a URL parameter, `name=value` for a \
+             data field or `name:=json` for a raw JSON field")))),
+    };
+    let variant = SEPARATOR_MAP.get(separator).unwrap().clone();
+
+    if key.text.is_empty() {
+        return Err(error(separator_offset, format!("Missing a name before `{}`", separator), None));
+    }
+    match variant {
+        RequestItemType::HTTPHeader | RequestItemType::EmptyHTTPHeader | RequestItemType::FileHTTPHeader => {
+            if let Some(index) = key.text.bytes().position(|b| !is_token_byte(b)) {
+                let c = key.text[index..].chars().next().unwrap();
+                let suggestion = if c == ' ' {
+                    Some(String::from("header names can't contain spaces, quote the whole item if the space belongs to the value"))
+                } else {
+                    None
+                };
+                return Err(error(key.offset_of(index, separator_offset),
+                                 format!("`{}` is not allowed in a header name", c), suggestion));
             }
-        } else if let TokenisedItem::Escaped(tok) = token {
-            // If separator not found yet, this is part of key
-            if let None = separator {
-                key.push_str(tok.as_str());
-            } else {
-                value.push_str(tok.as_str());
+        },
+        _ => (),
+    }
+    match variant {
+        // `Header;` can't carry a value, that's what `Header:value` is for
+        RequestItemType::EmptyHTTPHeader if !value.text.is_empty() => {
+            return Err(error(value.offset_of(0, v.len()),
+                             String::from("`;` sends a header with an empty value, so nothing may follow it"),
+                             Some(format!("use `{}:{}` to send a header with a value", key.text, value.text))));
+        },
+        RequestItemType::FileHTTPHeader | RequestItemType::FileURLParameter | RequestItemType::FileDataField
+            | RequestItemType::FileJsonData | RequestItemType::FormFile if value.text.is_empty() => {
+            return Err(error(v.len(), format!("Missing a file path after `{}`", separator),
+                             Some(String::from("use `-` to read from stdin"))));
+        },
+        RequestItemType::JsonData => {
+            if let Err(e) = serde_json::from_str::<Value>(&value.text) {
+                let index = json_error_index(&value.text, e.line(), e.column());
+                let suggestion = if looks_like_a_word(&value.text) {
+                    Some(format!("did you mean `{}={}` for a string?", key.text, value.text))
+                } else {
+                    None
+                };
+                return Err(error(value.offset_of(index, v.len()), format!("Invalid JSON after `:=`: {}", e),
+                                 suggestion));
             }
-        }
+        },
+        _ => (),
     }
 
-    separator.map(move |sep| {
-        let variant = SEPARATOR_MAP.get(sep).unwrap().clone();
-        let request_item = RequestItem {
+    Ok(ParsedItem {
+        item: RequestItem {
             variant: variant,
-            key: key,
-            value: value,
-        };
-        insert_to_cache(v, request_item.clone());
-        request_item
+            key: key.text,
+            value: value.text,
+        },
+        separator: separator,
+        separator_offset: separator_offset,
+        escaped: escaped,
     })
 }
 
+/// A suggestion for items which parse fine but probably don't do what was meant, such as
+/// `count=5` sending the string `"5"`
+pub fn suggestion(item: &RequestItem) -> Option<String> {
+    match item.variant {
+        RequestItemType::DataField => {
+            let kind = match serde_json::from_str::<Value>(&item.value) {
+                Ok(Value::Number(_)) => "a number",
+                Ok(Value::Bool(_)) => "a boolean",
+                Ok(Value::Null) => "null",
+                Ok(Value::Array(_)) => "an array",
+                Ok(Value::Object(_)) => "an object",
+                _ => return None,
+            };
+            Some(format!("this sends a string, did you mean `{}:={}` for {}?", item.key, item.value, kind))
+        },
+        _ => None,
+    }
+}
+
+/// The characters allowed in header names, the `tchar`s of RFC 7230
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+/// serde_json reports errors by line and column, both starting at 1
+fn json_error_index(text: &str, line: usize, column: usize) -> usize {
+    let line_start: usize = text.split('\n').take(line.saturating_sub(1)).map(|l| l.len() + 1).sum();
+    (line_start + column.saturating_sub(1)).min(text.len())
+}
+
+fn looks_like_a_word(text: &str) -> bool {
+    !text.is_empty() && text.chars().next().map(|c| c.is_alphabetic()).unwrap_or(false)
+        && !["true", "false", "null"].contains(&text)
+}
+
 fn tokenize(string: &str) -> Vec<TokenisedItem> {
     let mut tokens = Vec::new();
     let mut current = String::new();
+    let mut start = 0;
     let mut escaped = false;
-    for chr in string.chars() {
+    for (i, chr) in string.char_indices() {
         if chr == '\\' {
             escaped = true;
             continue;
         }
         if escaped {
             if SPECIAL_CHARS_SET.contains(&chr) {
-                tokens.push(TokenisedItem::Normal(current));
+                tokens.push(TokenisedItem::Normal(current, start));
                 current = String::new();
                 current.push(chr);
-                tokens.push(TokenisedItem::Escaped(current));
+                // The offset of the backslash
+                tokens.push(TokenisedItem::Escaped(current, i - 1));
                 current = String::new();
+                start = i + chr.len_utf8();
             } else {
                 current.push('\\');
                 current.push(chr);
@@ -143,7 +350,7 @@ fn tokenize(string: &str) -> Vec<TokenisedItem> {
         }
         escaped = false
     }
-    tokens.push(TokenisedItem::Normal(current));
+    tokens.push(TokenisedItem::Normal(current, start));
     tokens
 }
 
@@ -154,3 +361,133 @@ fn get_from_cache(v: &String) -> Option<RequestItem> {
 fn insert_to_cache(v: String, request_item: RequestItem) {
     CACHE.lock().unwrap().insert(v, request_item);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::RequestItemType::*;
+
+    fn parse(item: &str) -> Option<(String, String, RequestItemType)> {
+        get_request_item(String::from(item)).ok().map(|i| (i.key, i.value, i.variant))
+    }
+
+    fn item(key: &str, value: &str, variant: RequestItemType) -> Option<(String, String, RequestItemType)> {
+        Some((String::from(key), String::from(value), variant))
+    }
+
+    #[test]
+    fn header_with_value() {
+        assert_eq!(parse("X-Api-Key:secret"), item("X-Api-Key", "secret", HTTPHeader));
+    }
+
+    #[test]
+    fn header_without_value_unsets_it() {
+        assert_eq!(parse("User-Agent:"), item("User-Agent", "", HTTPHeader));
+    }
+
+    #[test]
+    fn header_with_semicolon_is_sent_empty() {
+        assert_eq!(parse("X-Empty;"), item("X-Empty", "", EmptyHTTPHeader));
+    }
+
+    #[test]
+    fn semicolon_header_cannot_have_a_value() {
+        assert_eq!(parse("X-Empty;value"), None);
+    }
+
+    #[test]
+    fn semicolons_in_header_values_are_kept() {
+        assert_eq!(parse("Link:<a>;rel"), item("Link", "<a>;rel", HTTPHeader));
+    }
+
+    #[test]
+    fn header_from_file() {
+        assert_eq!(parse("Authorization:@~/.token"), item("Authorization", "~/.token", FileHTTPHeader));
+    }
+
+    #[test]
+    fn url_parameter_from_file() {
+        assert_eq!(parse("signature==@-"), item("signature", "-", FileURLParameter));
+    }
+
+    #[test]
+    fn body_file_separators_are_unchanged() {
+        assert_eq!(parse("essay=@essay.txt"), item("essay", "essay.txt", FileDataField));
+        assert_eq!(parse("package:=@package.json"), item("package", "package.json", FileJsonData));
+    }
+
+    #[test]
+    fn leftmost_separator_wins() {
+        assert_eq!(parse("pass=a:@b"), item("pass", "a:@b", DataField));
+        assert_eq!(parse("q==a:=b"), item("q", "a:=b", URLParameter));
+        assert_eq!(parse("X-Query:a==b"), item("X-Query", "a==b", HTTPHeader));
+        assert_eq!(parse("Cookie:a=b;c=d"), item("Cookie", "a=b;c=d", HTTPHeader));
+    }
+
+    #[test]
+    fn longest_separator_wins_at_the_same_place() {
+        assert_eq!(parse("count:=5"), item("count", "5", JsonData));
+        assert_eq!(parse("q==rust"), item("q", "rust", URLParameter));
+        assert_eq!(parse("data:=@a=b.json"), item("data", "a=b.json", FileJsonData));
+    }
+
+    #[test]
+    fn escaped_semicolon_is_part_of_the_key() {
+        assert_eq!(parse("a\\;b;"), item("a;b", "", EmptyHTTPHeader));
+    }
+
+    fn error(item: &str) -> ItemParseError {
+        parse_request_item(item).unwrap_err()
+    }
+
+    #[test]
+    fn missing_separator_points_past_the_end() {
+        let e = error("name");
+        assert_eq!(e.offset, 4);
+        assert!(e.suggestion.is_some());
+    }
+
+    #[test]
+    fn empty_header_value_error_points_at_the_value() {
+        let e = error("X-Empty;value");
+        assert_eq!(e.offset, 8);
+        assert_eq!(e.suggestion, Some(String::from("use `X-Empty:value` to send a header with a value")));
+        assert_eq!(e.to_string(), "`;` sends a header with an empty value, so nothing may follow it\n\
+                                   \x20 X-Empty;value\n\
+                                   \x20         ^\n\
+                                   \x20 help: use `X-Empty:value` to send a header with a value");
+    }
+
+    #[test]
+    fn invalid_header_name_points_at_the_character() {
+        assert_eq!(error("Bad Header:value").offset, 3);
+        // Points at the escaped character rather than at the backslash before it
+        assert_eq!(error("a\\=b:c").offset, 2);
+    }
+
+    #[test]
+    fn invalid_json_suggests_a_string() {
+        let e = error("name:=chrome");
+        assert_eq!(e.offset, 6);
+        assert_eq!(e.suggestion, Some(String::from("did you mean `name=chrome` for a string?")));
+    }
+
+    #[test]
+    fn invalid_json_points_into_the_value() {
+        assert_eq!(error("list:=[1, 2,]").offset, 12);
+    }
+
+    #[test]
+    fn missing_name_and_file_path() {
+        assert_eq!(error("=value").offset, 0);
+        assert_eq!(error("token:@").offset, 7);
+    }
+
+    #[test]
+    fn numbers_in_data_fields_get_a_suggestion() {
+        let item = get_request_item(String::from("count=5")).unwrap();
+        assert_eq!(suggestion(&item), Some(String::from("this sends a string, did you mean `count:=5` for a number?")));
+        let item = get_request_item(String::from("name=chrome")).unwrap();
+        assert_eq!(suggestion(&item), None);
+    }
+}
@@ -1,63 +1,232 @@
 use actix_web::HttpMessage;
 use actix_web::client::ClientResponse;
-use actix_web::error::ContentTypeError;
+use actix_web::http::{StatusCode, Version};
+use actix_web::http::header::{CONTENT_TYPE, HeaderMap};
 use bytes::Bytes;
+use encoding::EncodingRef;
 use futures::Future;
-use serde_json::{to_string_pretty, from_slice, Value};
+use mime::Mime;
+use serde_json::{from_str, Value};
 
-use cli::Config;
+use charset;
 use errors::ChromeError;
+use expect::{self, Actual, Expectation};
+use filter::Filter;
 use output::*;
+use schema::Schema;
+use timing::{Timing, TimingFormat};
 
-pub fn process_response(config: &Config, res: ClientResponse) -> impl Future<Item = bool, Error = ChromeError> {
+use std::rc::Rc;
+use std::str;
+use std::time::Instant;
+
+// Allow for images and archives which are bigger than actix's default limit of 256kB
+pub static MAX_BODY_SIZE: usize = 100 * 1024 * 1024;
+
+/// A response independent of the protocol version it was received with
+pub struct Response {
+    /// The version which was actually negotiated with the server
+    pub version: Version,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The complete, decompressed body
+    pub body: Box<Future<Item = Bytes, Error = ChromeError>>,
+}
+
+impl Response {
+    fn mime_type(&self) -> Option<Mime> {
+        self.headers.get(CONTENT_TYPE)?.to_str().ok()?.parse().ok()
+    }
+}
+
+impl From<ClientResponse> for Response {
+    fn from(res: ClientResponse) -> Response {
+        Response {
+            version: res.version(),
+            status: res.status(),
+            headers: res.headers().clone(),
+            body: Box::new(res.body().limit(MAX_BODY_SIZE).from_err()),
+        }
+    }
+}
+
+/// How `process_response` prints a response and decides whether it was a success
+#[derive(Clone)]
+pub struct ResponseOptions {
+    pub interactive_output: bool,
+    pub term_width: usize,
+    pub term_height: usize,
+    /// Print the status and headers even when stdout isn't a terminal
+    pub verbose: bool,
+    pub response_charset: Option<EncodingRef>,
+    pub filter: Option<Filter>,
+    pub timing: Option<TimingFormat>,
+    pub paging_mode: PagingMode,
+    /// When there are any, they decide the outcome rather than the status
+    pub expectations: Vec<Expectation>,
+    pub schema: Option<Rc<Schema>>,
+}
+
+/// Prints the response, and resolves to whether it was a success
+pub fn process_response(printer: &Printer, options: &ResponseOptions, res: Response, timing: Timing)
+    -> impl Future<Item = bool, Error = ChromeError> {
     let mut response_str = String::new();
-    if config.interactive_output || config.verbose {
-        let first_line = format!("{:?} {} {}\n", res.version(), res.status().as_u16(),
-                                  res.status().canonical_reason().unwrap_or(""));
+    if options.interactive_output || options.verbose {
+        let first_line = format!("{} {} {}\n", format_version(res.version), res.status.as_u16(),
+                                  res.status.canonical_reason().unwrap_or(""));
         response_str.push_str(&first_line);
-        for (key, value) in res.headers().iter() {
-            let headerval_pair = format!("{}: {}\n", key.as_str(), value.to_str().expect(""));
-            response_str.push_str(&headerval_pair);
-        }
+        response_str.push_str(&printer.options.format_options.format_headers(&res.headers));
     }
 
-    let mime_type = res.mime_type();
-    let success = res.status().is_success();
+    let (mime_type, content_charset) = match res.mime_type() {
+        Some(m) => (
+            Some(format!("{}/{}", m.type_(), m.subtype())),
+            m.get_param("charset").map(|c| String::from(c.as_str())),
+        ),
+        None => (None, None),
+    };
+    let success = res.status.is_success();
+    let status = res.status;
+    let headers = res.headers.clone();
+    let expectations = options.expectations.clone();
+    let response_schema = options.schema.clone();
 
-    let colored = config.colored_output;
-    let true_color = config.true_color;
-    let only_body = !(config.interactive_output || config.verbose);
+    let printer = printer.clone();
+    let only_body = !(options.interactive_output || options.verbose);
+    let response_charset = options.response_charset;
+    let interactive = options.interactive_output;
+    let filter = options.filter.clone();
+    let format_options = printer.options.format_options;
+    let paging_mode = options.paging_mode;
+    let (term_width, term_height) = (options.term_width, options.term_height);
 
-    res.body()
-        .from_err()
+    let timing_format = options.timing;
+    let download_start = Instant::now();
+
+    res.body
         .and_then(move |bytes: Bytes| {  // <- complete body
+            let timing = Timing { download: download_start.elapsed(), ..timing };
+
+            let content_charset = content_charset.as_ref().map(String::as_str);
+            // Decoded once, and left alone for binary bodies which aren't text in any charset
+            let text = if is_binary(mime_type.as_ref().map(String::as_str), content_charset,
+                                    response_charset.is_some(), &bytes) {
+                None
+            } else {
+                Some(charset::decode(&bytes, content_charset, response_charset))
+            };
 
-            mime_type
-                .and_then(|option_mime| option_mime.ok_or(ContentTypeError::UnknownEncoding))
-                .map(|m| {
-                    let cur_mime = format!("{}/{}", m.type_(), m.subtype());
-                    let expected_mime = String::from("application/json");
-                    cur_mime == expected_mime
-                })
-                .and_then(|is_json| {
-                    if is_json {
-                        from_slice::<Value>(&bytes)
-                            .and_then(|x| to_string_pretty(&x))
+            let output = match text {
+                None if interactive => Body::Binary(bytes.len(), mime_type),
+                None => Body::Raw(bytes.clone()),
+                Some(ref text) => {
+                    let is_json = mime_type.map(|m| m == "application/json").unwrap_or(false);
+                    if let Some(filter) = filter {
+                        // The filter is applied regardless of the content type as plenty of
+                        // servers send json with the wrong one
+                        let value = from_str::<Value>(text).map_err(|_| ChromeError::FilterError {
+                            message: String::from("The response body is not valid JSON"),
+                        })?;
+                        let results = filter.apply(&value)
+                            .map_err(|message| ChromeError::FilterError { message: message })?;
+                        let mut pretty = Vec::new();
+                        for result in results {
+                            match result {
+                                // Strings are printed as they are when piped, e.g. into xargs
+                                Value::String(s) if !interactive => pretty.push(s),
+                                result => pretty.push(format_options.format_json(&result)?),
+                            }
+                        }
+                        Body::Json(pretty.join("\n"))
+                    } else if is_json {
+                        from_str::<Value>(text)
+                            .and_then(|x| format_options.format_json(&x))
                             .map(|s| Body::Json(s))
-                            .map_err(|_| ContentTypeError::ParseError)
+                            .unwrap_or(Body::Form(text.clone()))
                     } else {
-                        // unwrap_or will automatically handle this case
-                        // so this isn't exactly a parse error
-                        Err(ContentTypeError::ParseError)
+                        Body::Form(text.clone())
                     }
-                })
-                .or(
-                    String::from_utf8(bytes.to_vec())
-                        .map(|s| Body::Form(s))
-                        .map_err(|_| ChromeError::UnexpectedError)
-                )
-                .map(|output| print_http(response_str, output, colored, true_color, only_body))
+                },
+            };
+
+            let mut rendered = Vec::new();
+            printer.print_http(&mut rendered, response_str, output, only_body)?;
+            write_output(&rendered, paging_mode, term_width, term_height)?;
+
+            if let Some(format) = timing_format {
+                timing.print(format, printer.options.colored_output)?;
+            }
+
+            if expectations.is_empty() && response_schema.is_none() {
+                return Ok(success);
+            }
+            let json = text.as_ref().and_then(|text| from_str::<Value>(text).ok());
+            let colored = printer.options.colored_output;
+            let matches_schema = match response_schema {
+                Some(ref schema) => schema.check_body("response body", json.as_ref(), &format_options, colored),
+                None => Ok(()),
+            };
+            if expectations.is_empty() {
+                return matches_schema.map(|_| success);
+            }
+
+            // When there are expectations they decide the outcome, so that e.g. a 404 can be
+            // expected as well
+            let actual = Actual {
+                status: status,
+                headers: &headers,
+                json: json.as_ref(),
+                text: text.as_ref().map(String::as_str).unwrap_or(""),
+                time: timing.total(),
+            };
+            let failed = expect::check_all(&expectations, &actual, colored)?;
+            matches_schema?;
+            if failed > 0 {
+                return Err(ChromeError::ExpectationFailed { failed: failed, total: expectations.len() });
+            }
+            Ok(true)
         })
-        .map(move |_| success)
+}
+
+/// Decides whether a body should be treated as binary data, first by looking at its mime
+/// type and otherwise by sniffing for NUL bytes or invalid UTF-8.
+fn is_binary(mime_type: Option<&str>, content_charset: Option<&str>, charset_forced: bool, bytes: &[u8]) -> bool {
+    if mime_type.map(is_binary_mime).unwrap_or(false) {
+        return true;
+    }
+    if charset_forced || charset::is_declared(bytes, content_charset) {
+        return false;
+    }
+    if bytes.contains(&0) {
+        return true;
+    }
+    if mime_type.map(is_text_mime).unwrap_or(false) {
+        return false;
+    }
+    str::from_utf8(bytes).is_err()
+}
+
+fn is_text_mime(mime_type: &str) -> bool {
+    let (type_, subtype) = split_mime(mime_type);
+    type_ == "text"
+        || subtype.ends_with("+json")
+        || subtype.ends_with("+xml")
+        || ["json", "xml", "javascript", "ecmascript", "x-www-form-urlencoded", "graphql",
+            "yaml", "x-yaml", "x-sh", "sql"].contains(&subtype)
+}
+
+fn is_binary_mime(mime_type: &str) -> bool {
+    if is_text_mime(mime_type) {
+        return false;
+    }
+    let (type_, subtype) = split_mime(mime_type);
+    ["image", "audio", "video", "font"].contains(&type_)
+        || subtype.contains("protobuf")
+        || ["octet-stream", "pdf", "zip", "gzip", "x-gzip", "x-tar", "x-bzip2", "x-7z-compressed",
+            "grpc", "msgpack", "x-msgpack", "wasm", "vnd.ms-excel", "msword"].contains(&subtype)
+}
 
+fn split_mime(mime_type: &str) -> (&str, &str) {
+    let mut parts = mime_type.splitn(2, '/');
+    (parts.next().unwrap_or(""), parts.next().unwrap_or(""))
 }
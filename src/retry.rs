@@ -0,0 +1,232 @@
+use actix_web::client::SendRequestError;
+use actix_web::http::Method;
+use actix_web::http::header::{HttpDate, RETRY_AFTER};
+use ansi_term::Colour::Yellow;
+use futures::future::{self, Future, Loop};
+use rand;
+use tokio_timer::Delay;
+
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+use errors::ChromeError;
+use response::Response;
+use timing::{Timing, from_secs_f64};
+
+static BASE_DELAY_SECS: f64 = 0.5;
+static MAX_DELAY_SECS: f64 = 30.0;
+
+pub type Attempt = Box<Future<Item = (Response, Timing), Error = ChromeError>>;
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub on_status: Vec<u16>,
+    pub on_connect: bool,
+    pub max_time: Option<Duration>,
+    pub all_methods: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            retries: 0,
+            on_status: vec![502, 503, 504],
+            on_connect: true,
+            max_time: None,
+            all_methods: false,
+        }
+    }
+}
+
+pub fn is_retry_on(v: String) -> Result<(), String> {
+    parse_retry_on(&v).map(|_| ())
+}
+
+/// Parses `502,503,504,connect` into the status codes and whether to retry on connection errors
+pub fn parse_retry_on(v: &str) -> Result<(Vec<u16>, bool), String> {
+    let mut statuses = Vec::new();
+    let mut on_connect = false;
+    for condition in v.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        if condition == "connect" {
+            on_connect = true;
+        } else {
+            match condition.parse::<u16>() {
+                Ok(status) if status >= 100 && status < 600 => statuses.push(status),
+                _ => return Err(format!("Expected a status code or 'connect', got '{}'", condition)),
+            }
+        }
+    }
+    Ok((statuses, on_connect))
+}
+
+impl RetryPolicy {
+    /// Only idempotent methods are retried, unless `--retry-all` was given
+    pub fn applies_to(&self, method: &Method) -> bool {
+        if self.retries == 0 {
+            return false;
+        }
+        self.all_methods || match *method {
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE => true,
+            _ => false,
+        }
+    }
+
+    fn retries_status(&self, status: u16) -> bool {
+        self.on_status.contains(&status)
+    }
+
+    fn retries_error(&self, error: &ChromeError) -> bool {
+        self.on_connect && match *error {
+            ChromeError::ConnectionError { .. } | ChromeError::IoError { .. } => true,
+            ChromeError::Http2Error { ref error } => error.is_io(),
+            ChromeError::SendRequestFailed { ref error } => match *error {
+                SendRequestError::Timeout | SendRequestError::Connector(_) | SendRequestError::Io(_) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Exponential backoff with full jitter, unless the server asked for a specific delay.
+    /// Either way it is at most `MAX_DELAY_SECS`, and a delay which doesn't fit in `max_time`
+    /// ends the retries in `has_time_for`.
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let max = from_secs_f64(MAX_DELAY_SECS);
+        match retry_after {
+            Some(delay) if delay > max => max,
+            Some(delay) => delay,
+            None => {
+                let exponential = BASE_DELAY_SECS * 2f64.powi(attempt as i32);
+                let capped = if exponential > MAX_DELAY_SECS { MAX_DELAY_SECS } else { exponential };
+                from_secs_f64(capped * rand::random::<f64>())
+            },
+        }
+    }
+
+    /// Whether there is still time for another attempt after waiting for `delay`
+    fn has_time_for(&self, started: Instant, delay: Duration) -> bool {
+        self.max_time.map(|max| started.elapsed() + delay < max).unwrap_or(true)
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an http date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = SystemTime::from(HttpDate::from_str(value).ok()?);
+    Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+fn log_retry(attempt: u32, retries: u32, reason: &str, delay: Duration) {
+    eprintln!("{}: attempt {}/{} {}, retrying in {:.1}s", Yellow.paint("[chrome retry]"),
+              attempt + 1, retries + 1, reason, delay.as_secs() as f64 + delay.subsec_nanos() as f64 / 1e9);
+}
+
+/// Runs `attempt` until it succeeds, fails in a way the policy doesn't retry, or the policy
+/// runs out of attempts or time.
+pub fn with_retries<F>(policy: RetryPolicy, attempt: F) -> Attempt
+    where F: Fn() -> Attempt + 'static
+{
+    let policy = Rc::new(policy);
+    let started = Instant::now();
+
+    Box::new(future::loop_fn(0, move |n| {
+        let policy = policy.clone();
+        attempt().then(move |result| {
+            let retry = match result {
+                Ok((ref response, _)) if policy.retries_status(response.status.as_u16()) => {
+                    let reason = format!("got {}", response.status);
+                    Some((reason, retry_after(response)))
+                },
+                Err(ref error) if policy.retries_error(error) => Some((format!("failed: {}", error), None)),
+                _ => None,
+            };
+
+            match retry {
+                Some((reason, retry_after)) if n < policy.retries => {
+                    let delay = policy.delay(n, retry_after);
+                    if !policy.has_time_for(started, delay) {
+                        return future::Either::A(future::result(result.map(Loop::Break)));
+                    }
+                    log_retry(n, policy.retries, &reason, delay);
+                    future::Either::B(Delay::new(Instant::now() + delay)
+                        .map_err(|_| ChromeError::UnexpectedError)
+                        .map(move |_| Loop::Continue(n + 1)))
+                },
+                _ => future::Either::A(future::result(result.map(Loop::Break))),
+            }
+        })
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy { retries: retries, ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn retry_on_takes_statuses_and_connect() {
+        assert_eq!(parse_retry_on("502, 503,connect"), Ok((vec![502, 503], true)));
+        assert_eq!(parse_retry_on("429"), Ok((vec![429], false)));
+        assert_eq!(parse_retry_on("connect"), Ok((vec![], true)));
+    }
+
+    #[test]
+    fn retry_on_rejects_anything_else() {
+        assert_eq!(parse_retry_on("600"), Err(String::from("Expected a status code or 'connect', got '600'")));
+        assert!(parse_retry_on("99").is_err());
+        assert!(parse_retry_on("timeout").is_err());
+    }
+
+    #[test]
+    fn only_idempotent_methods_are_retried() {
+        let policy = policy(3);
+        assert!(policy.applies_to(&Method::GET));
+        assert!(policy.applies_to(&Method::PUT));
+        assert!(policy.applies_to(&Method::DELETE));
+        assert!(!policy.applies_to(&Method::POST));
+        assert!(!policy.applies_to(&Method::PATCH));
+
+        let all = RetryPolicy { all_methods: true, ..policy };
+        assert!(all.applies_to(&Method::POST));
+    }
+
+    #[test]
+    fn nothing_is_retried_without_retries() {
+        let policy = RetryPolicy { all_methods: true, ..policy(0) };
+        assert!(!policy.applies_to(&Method::GET));
+        assert!(!policy.applies_to(&Method::POST));
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = policy(10);
+        for attempt in 0..10 {
+            let delay = policy.delay(attempt, None);
+            assert!(delay <= from_secs_f64(BASE_DELAY_SECS * 2f64.powi(attempt as i32)), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(30), "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn retry_after_is_used_but_capped() {
+        let policy = policy(3);
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(2))), Duration::from_secs(2));
+        assert_eq!(policy.delay(0, Some(Duration::from_secs(3600))), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn delays_past_max_time_stop_the_retries() {
+        let policy = RetryPolicy { max_time: Some(Duration::from_secs(5)), ..policy(3) };
+        let started = Instant::now();
+        assert!(policy.has_time_for(started, Duration::from_secs(1)));
+        assert!(!policy.has_time_for(started, Duration::from_secs(30)));
+    }
+}
@@ -0,0 +1,746 @@
+use ansi_term::Colour::Red;
+use regex::Regex;
+use serde_json::{self, Map, Number, Value};
+use url::percent_encoding::percent_decode;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use errors::ChromeError;
+use files::expand_path;
+use format::{FormatOptions, pointer_segment};
+
+/// How deep `$ref`s may nest before a schema is taken to refer to itself in a loop
+static MAX_DEPTH: usize = 64;
+
+/// How many lines of the body are shown around the ones with errors
+static CONTEXT_LINES: usize = 2;
+
+/// How many errors are listed before the rest are only counted
+static MAX_LISTED: usize = 20;
+
+/// A JSON Schema (draft 7 or 2020-12) loaded from a file, along with the local files it
+/// refers to with `$ref`. References are resolved relative to the file they are in. Keywords
+/// which can't be checked this way, such as `$dynamicRef` or a nested `$id` with a base URI,
+/// are rejected when the schema is loaded rather than ignored.
+pub struct Schema {
+    /// As it was given, for the report
+    pub path: String,
+    root: PathBuf,
+    documents: HashMap<PathBuf, Value>,
+    /// Where the file part of each `$ref` leads, by the document it is in
+    files: HashMap<(PathBuf, String), PathBuf>,
+    patterns: HashMap<String, Regex>,
+}
+
+/// A part of the instance which doesn't match the schema
+#[derive(Debug, PartialEq)]
+pub struct ValidationError {
+    /// A JSON pointer to the part, e.g. `/items/0/id`, which is empty for the whole instance
+    pub pointer: String,
+    pub message: String,
+}
+
+impl Schema {
+    pub fn load(path: &str) -> Result<Schema, ChromeError> {
+        let root = fs::canonicalize(expand_path(path)).map_err(|e| ChromeError::SchemaError {
+            path: String::from(path),
+            message: e.to_string(),
+        })?;
+        let document = read_document(&root)?;
+        Schema::from_document(String::from(path), root, document)
+    }
+
+    fn from_document(path: String, root: PathBuf, document: Value) -> Result<Schema, ChromeError> {
+        let mut schema = Schema {
+            path: path,
+            root: root.clone(),
+            documents: HashMap::new(),
+            files: HashMap::new(),
+            patterns: HashMap::new(),
+        };
+        schema.add_document(root, document)?;
+        Ok(schema)
+    }
+
+    /// Compiles the patterns in `document` and loads the files it refers to, so that neither
+    /// can fail once validation has started
+    fn add_document(&mut self, path: PathBuf, document: Value) -> Result<(), ChromeError> {
+        let error = |message: String| ChromeError::SchemaError { path: path.display().to_string(), message: message };
+
+        if let Some(message) = find_unsupported(&document, true) {
+            return Err(error(message));
+        }
+        let (mut references, mut patterns) = (Vec::new(), Vec::new());
+        collect_keywords(&document, &mut references, &mut patterns);
+        for pattern in patterns {
+            if !self.patterns.contains_key(&pattern) {
+                let regex = Regex::new(&pattern).map_err(|e| error(format!("Invalid pattern '{}': {}", pattern, e)))?;
+                self.patterns.insert(pattern, regex);
+            }
+        }
+        self.documents.insert(path.clone(), document);
+
+        for reference in references {
+            let file = String::from(reference.split('#').next().unwrap_or(""));
+            if file.is_empty() || self.files.contains_key(&(path.clone(), file.clone())) {
+                continue;
+            }
+            if file.contains("://") {
+                return Err(error(format!("Only local files can be referenced, not {}", file)));
+            }
+            let target = fs::canonicalize(path.parent().unwrap_or(Path::new(".")).join(&file))
+                .map_err(|e| error(format!("Can't read {}: {}", file, e)))?;
+            self.files.insert((path.clone(), file), target.clone());
+            if !self.documents.contains_key(&target) {
+                let document = read_document(&target)?;
+                self.add_document(target, document)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Every part of `instance` which doesn't match the schema
+    pub fn validate(&self, instance: &Value) -> Vec<ValidationError> {
+        self.errors_for(&self.documents[&self.root], &self.root, instance, "", 0)
+    }
+
+    /// Validates a body, which is `None` if it isn't JSON, and reports where it doesn't match
+    /// to stderr
+    pub fn check_body(&self, what: &str, body: Option<&Value>, format_options: &FormatOptions, colored: bool) -> Result<(), ChromeError> {
+        let errors = match body {
+            Some(body) => self.validate(body),
+            None => vec![ValidationError { pointer: String::new(), message: format!("the {} isn't JSON", what) }],
+        };
+        if errors.is_empty() {
+            return Ok(());
+        }
+        report(what, &self.path, &errors, body, format_options, colored)?;
+        Err(ChromeError::SchemaMismatch { what: String::from(what), path: self.path.clone() })
+    }
+
+    fn errors_for(&self, schema: &Value, base: &Path, instance: &Value, pointer: &str, depth: usize) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        self.check(schema, base, instance, pointer, depth, &mut errors);
+        errors
+    }
+
+    fn is_valid(&self, schema: &Value, base: &Path, instance: &Value, pointer: &str, depth: usize) -> bool {
+        self.errors_for(schema, base, instance, pointer, depth).is_empty()
+    }
+
+    /// `base` is the file `schema` is in, which its `$ref`s are relative to
+    fn check(&self, schema: &Value, base: &Path, instance: &Value, pointer: &str, depth: usize, errors: &mut Vec<ValidationError>) {
+        let schema = match *schema {
+            Value::Object(ref schema) => schema,
+            Value::Bool(false) => return push(errors, pointer, String::from("no value is allowed here")),
+            // `true`, and anything else which isn't a schema, allows everything
+            _ => return,
+        };
+        if depth > MAX_DEPTH {
+            return push(errors, pointer, String::from("$ref nests too deep, it may refer to itself"));
+        }
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            match self.resolve(base, reference) {
+                Some((target, target_base)) => self.check(target, target_base, instance, pointer, depth + 1, errors),
+                None => push(errors, pointer, format!("$ref {} can't be resolved", reference)),
+            }
+            // Before 2019-09 the keywords next to a `$ref` were ignored
+            if self.is_legacy(base) {
+                return;
+            }
+        }
+
+        check_value(schema, instance, pointer, errors);
+        match *instance {
+            Value::Number(ref n) => check_number(schema, n.as_f64().unwrap_or(0.0), pointer, errors),
+            Value::String(ref s) => self.check_string(schema, s, pointer, errors),
+            Value::Array(ref items) => self.check_array(schema, base, items, pointer, depth, errors),
+            Value::Object(_) => self.check_object(schema, base, instance, pointer, depth, errors),
+            _ => (),
+        }
+        self.check_combinators(schema, base, instance, pointer, depth, errors);
+    }
+
+    fn resolve<'a>(&'a self, base: &'a Path, reference: &str) -> Option<(&'a Value, &'a Path)> {
+        let mut parts = reference.splitn(2, '#');
+        let file = parts.next().unwrap_or("");
+        let fragment = percent_decode(parts.next().unwrap_or("").as_bytes()).decode_utf8().ok()?;
+        let path = if file.is_empty() {
+            base
+        } else {
+            self.files.get(&(base.to_path_buf(), String::from(file)))?.as_path()
+        };
+        let document = self.documents.get(path)?;
+        let target = if fragment.is_empty() {
+            document
+        } else if fragment.starts_with('/') {
+            document.pointer(&fragment)?
+        } else {
+            find_anchor(document, &fragment)?
+        };
+        Some((target, path))
+    }
+
+    /// Whether the document declares a draft from before 2019-09
+    fn is_legacy(&self, base: &Path) -> bool {
+        self.documents.get(base)
+            .and_then(|document| document.get("$schema"))
+            .and_then(Value::as_str)
+            .map(|uri| ["draft-04", "draft-06", "draft-07"].iter().any(|draft| uri.contains(*draft)))
+            .unwrap_or(false)
+    }
+
+    fn check_string(&self, schema: &Map<String, Value>, s: &str, pointer: &str, errors: &mut Vec<ValidationError>) {
+        let length = s.chars().count() as u64;
+        if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+            if length > max {
+                push(errors, pointer, format!("expected at most {} characters, got {}", max, length));
+            }
+        }
+        if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+            if length < min {
+                push(errors, pointer, format!("expected at least {} characters, got {}", min, length));
+            }
+        }
+        if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+            if self.patterns.get(pattern).map(|regex| !regex.is_match(s)).unwrap_or(false) {
+                push(errors, pointer, format!("expected to match /{}/, got {}", pattern, describe(&Value::from(s))));
+            }
+        }
+    }
+
+    fn check_array(&self, schema: &Map<String, Value>, base: &Path, items: &[Value], pointer: &str, depth: usize, errors: &mut Vec<ValidationError>) {
+        let at = |i: usize| format!("{}/{}", pointer, i);
+
+        // `prefixItems` and `items` in 2020-12, `items` as an array and `additionalItems` before
+        let (prefix, rest) = match (schema.get("prefixItems"), schema.get("items")) {
+            (Some(&Value::Array(ref prefix)), rest) => (&prefix[..], rest),
+            (None, Some(&Value::Array(ref prefix))) => (&prefix[..], schema.get("additionalItems")),
+            (_, rest) => (&[][..], rest),
+        };
+        for (i, (item, item_schema)) in items.iter().zip(prefix).enumerate() {
+            self.check(item_schema, base, item, &at(i), depth, errors);
+        }
+        if let Some(rest) = rest {
+            for (i, item) in items.iter().enumerate().skip(prefix.len()) {
+                self.check(rest, base, item, &at(i), depth, errors);
+            }
+        }
+
+        if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+            if items.len() as u64 > max {
+                push(errors, pointer, format!("expected at most {} items, got {}", max, items.len()));
+            }
+        }
+        if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+            if (items.len() as u64) < min {
+                push(errors, pointer, format!("expected at least {} items, got {}", min, items.len()));
+            }
+        }
+        if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+            for (j, item) in items.iter().enumerate() {
+                if let Some(i) = items[..j].iter().position(|other| equal(other, item)) {
+                    push(errors, &at(j), format!("expected unique items, this is the same as item {}", i));
+                }
+            }
+        }
+
+        if let Some(contains) = schema.get("contains") {
+            let matching = items.iter().enumerate()
+                .filter(|&(i, item)| self.is_valid(contains, base, item, &at(i), depth))
+                .count() as u64;
+            let min = schema.get("minContains").and_then(Value::as_u64).unwrap_or(1);
+            if matching < min {
+                push(errors, pointer, format!("expected at least {} items matching 'contains', got {}", min, matching));
+            }
+            if let Some(max) = schema.get("maxContains").and_then(Value::as_u64) {
+                if matching > max {
+                    push(errors, pointer, format!("expected at most {} items matching 'contains', got {}", max, matching));
+                }
+            }
+        }
+    }
+
+    fn check_object(&self, schema: &Map<String, Value>, base: &Path, instance: &Value, pointer: &str, depth: usize, errors: &mut Vec<ValidationError>) {
+        let object = match *instance {
+            Value::Object(ref object) => object,
+            _ => return,
+        };
+        let at = |key: &str| format!("{}/{}", pointer, pointer_segment(key));
+        let none = Map::new();
+        let properties = match schema.get("properties") {
+            Some(&Value::Object(ref properties)) => properties,
+            _ => &none,
+        };
+        let patterns = match schema.get("patternProperties") {
+            Some(&Value::Object(ref patterns)) => patterns,
+            _ => &none,
+        };
+
+        for (key, value) in object {
+            let mut evaluated = false;
+            if let Some(property) = properties.get(key) {
+                self.check(property, base, value, &at(key), depth, errors);
+                evaluated = true;
+            }
+            for (pattern, property) in patterns {
+                if self.patterns.get(pattern).map(|regex| regex.is_match(key)).unwrap_or(false) {
+                    self.check(property, base, value, &at(key), depth, errors);
+                    evaluated = true;
+                }
+            }
+            match schema.get("additionalProperties") {
+                Some(&Value::Bool(false)) if !evaluated => push(errors, &at(key), format!("property '{}' isn't allowed", key)),
+                Some(additional) if !evaluated => self.check(additional, base, value, &at(key), depth, errors),
+                _ => (),
+            }
+            if let Some(names) = schema.get("propertyNames") {
+                let name = Value::String(key.clone());
+                if let Some(error) = self.errors_for(names, base, &name, &at(key), depth).into_iter().next() {
+                    push(errors, &at(key), format!("invalid property name, {}", error.message));
+                }
+            }
+        }
+
+        if let Some(&Value::Array(ref required)) = schema.get("required") {
+            for name in required.iter().filter_map(Value::as_str) {
+                if !object.contains_key(name) {
+                    push(errors, pointer, format!("missing required property '{}'", name));
+                }
+            }
+        }
+        if let Some(max) = schema.get("maxProperties").and_then(Value::as_u64) {
+            if object.len() as u64 > max {
+                push(errors, pointer, format!("expected at most {} properties, got {}", max, object.len()));
+            }
+        }
+        if let Some(min) = schema.get("minProperties").and_then(Value::as_u64) {
+            if (object.len() as u64) < min {
+                push(errors, pointer, format!("expected at least {} properties, got {}", min, object.len()));
+            }
+        }
+
+        // `dependentRequired` and `dependentSchemas` in 2020-12, both were `dependencies` before
+        for keyword in &["dependencies", "dependentRequired", "dependentSchemas"] {
+            let dependencies = match schema.get(*keyword) {
+                Some(&Value::Object(ref dependencies)) => dependencies,
+                _ => continue,
+            };
+            for (key, dependency) in dependencies.iter().filter(|&(key, _)| object.contains_key(key)) {
+                match *dependency {
+                    Value::Array(ref names) => for name in names.iter().filter_map(Value::as_str) {
+                        if !object.contains_key(name) {
+                            push(errors, pointer, format!("missing property '{}', which '{}' requires", name, key));
+                        }
+                    },
+                    ref dependency => self.check(dependency, base, instance, pointer, depth, errors),
+                }
+            }
+        }
+    }
+
+    fn check_combinators(&self, schema: &Map<String, Value>, base: &Path, instance: &Value, pointer: &str, depth: usize, errors: &mut Vec<ValidationError>) {
+        if let Some(&Value::Array(ref all)) = schema.get("allOf") {
+            for subschema in all {
+                self.check(subschema, base, instance, pointer, depth, errors);
+            }
+        }
+        if let Some(&Value::Array(ref any)) = schema.get("anyOf") {
+            let failures = any.iter()
+                .map(|subschema| self.errors_for(subschema, base, instance, pointer, depth))
+                .collect::<Vec<_>>();
+            if !failures.iter().any(Vec::is_empty) {
+                push(errors, pointer, format!("expected to match one of the 'anyOf' schemas ({})", summarize(pointer, &failures)));
+            }
+        }
+        if let Some(&Value::Array(ref one)) = schema.get("oneOf") {
+            let failures = one.iter()
+                .map(|subschema| self.errors_for(subschema, base, instance, pointer, depth))
+                .collect::<Vec<_>>();
+            match failures.iter().filter(|failure| failure.is_empty()).count() {
+                0 => push(errors, pointer, format!("expected to match one of the 'oneOf' schemas ({})", summarize(pointer, &failures))),
+                1 => (),
+                matching => push(errors, pointer, format!("expected to match exactly one of the 'oneOf' schemas, matched {}", matching)),
+            }
+        }
+        if let Some(not) = schema.get("not") {
+            if self.is_valid(not, base, instance, pointer, depth) {
+                push(errors, pointer, String::from("expected not to match the 'not' schema"));
+            }
+        }
+        if let Some(condition) = schema.get("if") {
+            let branch = if self.is_valid(condition, base, instance, pointer, depth) {
+                schema.get("then")
+            } else {
+                schema.get("else")
+            };
+            if let Some(branch) = branch {
+                self.check(branch, base, instance, pointer, depth, errors);
+            }
+        }
+    }
+}
+
+fn read_document(path: &Path) -> Result<Value, ChromeError> {
+    let error = |message: String| ChromeError::SchemaError { path: path.display().to_string(), message: message };
+    let text = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    serde_json::from_str(&text).map_err(|e| error(e.to_string()))
+}
+
+/// Finds the `$ref`s and regular expressions in a schema, leaving out values such as those
+/// of `enum` which only look like schemas
+fn collect_keywords(schema: &Value, references: &mut Vec<String>, patterns: &mut Vec<String>) {
+    match *schema {
+        Value::Object(ref map) => for (key, value) in map {
+            match (key.as_str(), value) {
+                ("$ref", &Value::String(ref reference)) => references.push(reference.clone()),
+                ("pattern", &Value::String(ref pattern)) => patterns.push(pattern.clone()),
+                ("patternProperties", &Value::Object(ref properties)) => patterns.extend(properties.keys().cloned()),
+                ("enum", _) | ("const", _) | ("default", _) | ("examples", _) => continue,
+                _ => (),
+            }
+            collect_keywords(value, references, patterns);
+        },
+        Value::Array(ref items) => for item in items {
+            collect_keywords(item, references, patterns);
+        },
+        _ => (),
+    }
+}
+
+/// The first keyword in a schema that isn't supported, as an error message. Only the root
+/// of a document may have an `$id` with a base URI, which comes to the same as the file
+/// it is in for `$ref`s to local files.
+fn find_unsupported(schema: &Value, root: bool) -> Option<String> {
+    match *schema {
+        Value::Object(ref map) => map.iter().filter_map(|(key, value)| match (key.as_str(), value) {
+            ("unevaluatedProperties", _) | ("unevaluatedItems", _) | ("$dynamicRef", _) | ("$recursiveRef", _) =>
+                Some(format!("{} isn't supported", key)),
+            ("$id", &Value::String(ref id)) if !root && !id.starts_with('#') =>
+                Some(format!("$id {} isn't supported, $refs are resolved relative to the file they are in", id)),
+            ("enum", _) | ("const", _) | ("default", _) | ("examples", _) => None,
+            // Maps of names to schemas, where the names aren't keywords
+            ("properties", &Value::Object(ref schemas)) | ("patternProperties", &Value::Object(ref schemas))
+                | ("$defs", &Value::Object(ref schemas)) | ("definitions", &Value::Object(ref schemas))
+                | ("dependentSchemas", &Value::Object(ref schemas)) =>
+                schemas.values().filter_map(|schema| find_unsupported(schema, false)).next(),
+            _ => find_unsupported(value, false),
+        }).next(),
+        Value::Array(ref items) => items.iter().filter_map(|item| find_unsupported(item, false)).next(),
+        _ => None,
+    }
+}
+
+/// The subschema with `"$anchor": name`, or `"$id": "#name"` as in draft 7
+fn find_anchor<'a>(schema: &'a Value, name: &str) -> Option<&'a Value> {
+    match *schema {
+        Value::Object(ref map) => {
+            let anchor = map.get("$anchor").and_then(Value::as_str) == Some(name)
+                || map.get("$id").and_then(Value::as_str) == Some(format!("#{}", name).as_str());
+            if anchor {
+                return Some(schema);
+            }
+            map.values().filter_map(|value| find_anchor(value, name)).next()
+        },
+        Value::Array(ref items) => items.iter().filter_map(|item| find_anchor(item, name)).next(),
+        _ => None,
+    }
+}
+
+/// `type`, `enum` and `const`, which apply to any kind of value
+fn check_value(schema: &Map<String, Value>, instance: &Value, pointer: &str, errors: &mut Vec<ValidationError>) {
+    let types: Vec<&str> = match schema.get("type") {
+        Some(&Value::String(ref name)) => vec![name.as_str()],
+        Some(&Value::Array(ref names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|name| is_type(instance, name)) {
+        push(errors, pointer, format!("expected {}, got {}", types.join(" or "), type_name(instance)));
+    }
+    if let Some(&Value::Array(ref values)) = schema.get("enum") {
+        if !values.iter().any(|value| equal(value, instance)) {
+            let values = values.iter().map(describe).collect::<Vec<_>>();
+            push(errors, pointer, format!("expected one of {}, got {}", values.join(", "), describe(instance)));
+        }
+    }
+    if let Some(value) = schema.get("const") {
+        if !equal(value, instance) {
+            push(errors, pointer, format!("expected {}, got {}", describe(value), describe(instance)));
+        }
+    }
+}
+
+fn check_number(schema: &Map<String, Value>, n: f64, pointer: &str, errors: &mut Vec<ValidationError>) {
+    let limit = |keyword: &str| schema.get(keyword).and_then(Value::as_f64);
+    if let Some(factor) = limit("multipleOf") {
+        let quotient = n / factor;
+        if factor > 0.0 && (quotient - quotient.round()).abs() > 1e-9 {
+            push(errors, pointer, format!("expected a multiple of {}, got {}", factor, n));
+        }
+    }
+    if let Some(max) = limit("maximum") {
+        if n > max {
+            push(errors, pointer, format!("expected at most {}, got {}", max, n));
+        }
+    }
+    if let Some(max) = limit("exclusiveMaximum") {
+        if n >= max {
+            push(errors, pointer, format!("expected less than {}, got {}", max, n));
+        }
+    }
+    if let Some(min) = limit("minimum") {
+        if n < min {
+            push(errors, pointer, format!("expected at least {}, got {}", min, n));
+        }
+    }
+    if let Some(min) = limit("exclusiveMinimum") {
+        if n <= min {
+            push(errors, pointer, format!("expected more than {}, got {}", min, n));
+        }
+    }
+}
+
+fn push(errors: &mut Vec<ValidationError>, pointer: &str, message: String) {
+    errors.push(ValidationError { pointer: String::from(pointer), message: message });
+}
+
+fn is_type(value: &Value, name: &str) -> bool {
+    match (name, value) {
+        ("null", &Value::Null)
+        | ("boolean", &Value::Bool(_))
+        | ("number", &Value::Number(_))
+        | ("string", &Value::String(_))
+        | ("array", &Value::Array(_))
+        | ("object", &Value::Object(_)) => true,
+        ("integer", &Value::Number(ref n)) => is_integer(n),
+        _ => false,
+    }
+}
+
+/// Numbers such as 1.0 count as integers too
+fn is_integer(n: &Number) -> bool {
+    n.is_i64() || n.is_u64() || n.as_f64().map(|f| f.fract() == 0.0).unwrap_or(false)
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match *value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(ref n) if is_integer(n) => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Equality as JSON Schema sees it, where e.g. 1 and 1.0 are the same number
+fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (&Value::Number(ref a), &Value::Number(ref b)) => a.as_f64() == b.as_f64(),
+        (&Value::Array(ref a), &Value::Array(ref b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        },
+        (&Value::Object(ref a), &Value::Object(ref b)) => {
+            a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).map(|b| equal(a, b)).unwrap_or(false))
+        },
+        _ => a == b,
+    }
+}
+
+/// A value for an error message, cut short if it is long
+fn describe(value: &Value) -> String {
+    let text = value.to_string();
+    if text.chars().count() <= 40 {
+        return text;
+    }
+    format!("{}...", text.chars().take(37).collect::<String>())
+}
+
+fn display_pointer(pointer: &str) -> &str {
+    if pointer.is_empty() { "(root)" } else { pointer }
+}
+
+/// The first error of each alternative, e.g. `expected string, got integer; /id: ...`
+fn summarize(pointer: &str, failures: &[Vec<ValidationError>]) -> String {
+    failures.iter()
+        .filter_map(|errors| errors.first())
+        .map(|error| if error.pointer == pointer {
+            error.message.clone()
+        } else {
+            format!("{}: {}", display_pointer(&error.pointer), error.message)
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Lists the errors on stderr, followed by the lines of the body they point at with a few
+/// lines around them
+fn report(what: &str, path: &str, errors: &[ValidationError], body: Option<&Value>,
+          format_options: &FormatOptions, colored: bool) -> io::Result<()> {
+    let stderr = io::stderr();
+    let mut out = stderr.lock();
+    let red = |text: String| if colored { Red.paint(text).to_string() } else { text };
+
+    writeln!(out, "The {} doesn't match {}:", what, path)?;
+    for error in errors.iter().take(MAX_LISTED) {
+        writeln!(out, "{}", red(format!("  {}: {}", display_pointer(&error.pointer), error.message)))?;
+    }
+    if errors.len() > MAX_LISTED {
+        writeln!(out, "  and {} more", errors.len() - MAX_LISTED)?;
+    }
+
+    let lines = match body {
+        Some(body) => format_options.format_json_lines(body),
+        None => return Ok(()),
+    };
+    let marked = lines.iter()
+        .map(|&(ref pointer, _)| match *pointer {
+            Some(ref pointer) => errors.iter().any(|error| error.pointer == *pointer),
+            None => false,
+        })
+        .collect::<Vec<_>>();
+    let width = lines.len().to_string().len();
+    let mut skipped = false;
+    writeln!(out, "")?;
+    for (i, &(_, ref line)) in lines.iter().enumerate() {
+        let start = i.saturating_sub(CONTEXT_LINES);
+        let end = (i + CONTEXT_LINES + 1).min(lines.len());
+        if !marked[start..end].contains(&true) {
+            skipped = true;
+            continue;
+        }
+        if skipped {
+            writeln!(out, "  {:>w$}  ...", "", w = width)?;
+            skipped = false;
+        }
+        if marked[i] {
+            writeln!(out, "{}", red(format!("> {:>w$} | {}", i + 1, line, w = width)))?;
+        } else {
+            writeln!(out, "  {:>w$} | {}", i + 1, line, w = width)?;
+        }
+    }
+    if skipped {
+        writeln!(out, "  {:>w$}  ...", "", w = width)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(schema: &str, instance: &str) -> Vec<(String, String)> {
+        let schema = Schema::from_document(String::from("schema.json"), PathBuf::from("schema.json"),
+                                           serde_json::from_str(schema).unwrap()).unwrap();
+        schema.validate(&serde_json::from_str(instance).unwrap()).into_iter()
+            .map(|error| (error.pointer, error.message))
+            .collect()
+    }
+
+    fn pointers(schema: &str, instance: &str) -> Vec<String> {
+        errors(schema, instance).into_iter().map(|(pointer, _)| pointer).collect()
+    }
+
+    #[test]
+    fn reports_types_with_pointers() {
+        let schema = r#"{"type": "object", "properties": {"id": {"type": "integer"}, "tags": {"items": {"type": "string"}}}}"#;
+        assert_eq!(errors(schema, r#"{"id": "7", "tags": ["a", 1]}"#), vec![
+            (String::from("/id"), String::from("expected integer, got string")),
+            (String::from("/tags/1"), String::from("expected string, got integer")),
+        ]);
+        assert!(errors(schema, r#"{"id": 7.0, "tags": []}"#).is_empty());
+        assert_eq!(pointers(schema, "[]"), vec![""]);
+    }
+
+    #[test]
+    fn required_and_additional_properties() {
+        let schema = r#"{"required": ["id", "name"], "properties": {"id": {}}, "patternProperties": {"^x-": {}},
+                         "additionalProperties": false}"#;
+        assert_eq!(errors(schema, r#"{"id": 1, "x-tag": 2, "extra": 3}"#), vec![
+            (String::from("/extra"), String::from("property 'extra' isn't allowed")),
+            (String::from(""), String::from("missing required property 'name'")),
+        ]);
+        // Keys are escaped in pointers
+        assert_eq!(pointers(r#"{"additionalProperties": false}"#, r#"{"a/b~": 1}"#), vec!["/a~1b~0"]);
+    }
+
+    #[test]
+    fn numbers_strings_and_arrays() {
+        assert_eq!(pointers(r#"{"minimum": 1, "exclusiveMaximum": 10, "multipleOf": 0.5}"#, "10"), vec![""]);
+        assert!(pointers(r#"{"minimum": 1, "exclusiveMaximum": 10, "multipleOf": 0.5}"#, "2.5").is_empty());
+        assert_eq!(errors(r#"{"pattern": "^[a-z]+$", "maxLength": 3}"#, r#""abcD""#).len(), 2);
+        assert_eq!(pointers(r#"{"uniqueItems": true}"#, "[1, 2, 1.0]"), vec!["/2"]);
+        assert_eq!(pointers(r#"{"prefixItems": [{"type": "string"}], "items": false}"#, r#"["a", 1]"#), vec!["/1"]);
+        assert_eq!(pointers(r#"{"items": [{"type": "string"}], "additionalItems": false}"#, r#"["a", 1]"#), vec!["/1"]);
+        assert_eq!(pointers(r#"{"contains": {"const": 2}, "maxContains": 1}"#, "[2, 2]"), vec![""]);
+        assert!(pointers(r#"{"contains": {"const": 2}}"#, "[1, 2]").is_empty());
+    }
+
+    #[test]
+    fn combinators_and_conditionals() {
+        let schema = r#"{"anyOf": [{"type": "string"}, {"type": "null"}]}"#;
+        assert_eq!(errors(schema, "1"), vec![(String::from(""), String::from(
+            "expected to match one of the 'anyOf' schemas (expected string, got integer; expected null, got integer)"))]);
+        assert_eq!(pointers(r#"{"oneOf": [{"type": "number"}, {"type": "integer"}]}"#, "1"), vec![""]);
+        assert!(pointers(r#"{"oneOf": [{"type": "number"}, {"type": "integer"}]}"#, "1.5").is_empty());
+        assert_eq!(pointers(r#"{"not": {"enum": ["a", "b"]}}"#, r#""a""#), vec![""]);
+        let schema = r#"{"if": {"properties": {"kind": {"const": "user"}}}, "then": {"required": ["email"]}}"#;
+        assert_eq!(pointers(schema, r#"{"kind": "user"}"#), vec![""]);
+        assert!(pointers(schema, r#"{"kind": "team"}"#).is_empty());
+        assert_eq!(pointers(r#"{"dependentRequired": {"a": ["b"]}}"#, r#"{"a": 1}"#), vec![""]);
+    }
+
+    #[test]
+    fn local_references_and_anchors() {
+        let schema = r##"{
+            "$defs": {"id": {"$anchor": "id", "type": "integer"}, "a~b": {"type": "string"}},
+            "properties": {"id": {"$ref": "#/$defs/id"}, "other": {"$ref": "#id"}, "name": {"$ref": "#/$defs/a~0b"}}
+        }"##;
+        assert_eq!(pointers(schema, r#"{"id": "1", "other": "2", "name": 3}"#), vec!["/id", "/other", "/name"]);
+    }
+
+    #[test]
+    fn siblings_of_ref_are_ignored_before_2019_09() {
+        let schema = r##"{"$schema": "http://json-schema.org/draft-07/schema#", "definitions": {"n": {"type": "number"}},
+                         "$ref": "#/definitions/n", "minimum": 5}"##;
+        assert!(pointers(schema, "1").is_empty());
+        let schema = r##"{"$defs": {"n": {"type": "number"}}, "$ref": "#/$defs/n", "minimum": 5}"##;
+        assert_eq!(pointers(schema, "1"), vec![""]);
+    }
+
+    #[test]
+    fn references_which_loop_are_stopped() {
+        assert_eq!(pointers(r##"{"$ref": "#"}"##, "1"), vec![""]);
+    }
+
+    #[test]
+    fn unsupported_keywords_are_rejected() {
+        let load = |schema: &str| Schema::from_document(
+            String::from("schema.json"), PathBuf::from("schema.json"), serde_json::from_str(schema).unwrap(),
+        ).err().map(|e| e.to_string());
+
+        assert!(load(r#"{"unevaluatedProperties": false}"#).unwrap().contains("unevaluatedProperties isn't supported"));
+        assert!(load(r#"{"items": {"unevaluatedItems": false}}"#).unwrap().contains("unevaluatedItems isn't supported"));
+        assert!(load(r##"{"$defs": {"a": {"$dynamicRef": "#node"}}}"##).unwrap().contains("$dynamicRef isn't supported"));
+        assert!(load(r#"{"properties": {"a": {"$id": "https://example.com/a.json"}}}"#).unwrap().contains("$id https://example.com/a.json"));
+
+        assert_eq!(load(r#"{"$id": "https://example.com/root.json", "properties": {"unevaluatedItems": {}}}"#), None);
+        assert_eq!(load(r##"{"definitions": {"a": {"$id": "#a"}}, "enum": [{"$dynamicRef": "x"}]}"##), None);
+    }
+
+    #[test]
+    fn lines_of_formatted_json_know_their_pointer() {
+        let value: Value = serde_json::from_str(r#"{"a": [1, {}], "b": {"c/d": null}}"#).unwrap();
+        let options = FormatOptions::default();
+        let lines = options.format_json_lines(&value);
+        let text = lines.iter().map(|&(_, ref line)| line.as_str()).collect::<Vec<_>>().join("\n");
+        assert_eq!(text, options.format_json(&value).unwrap());
+        let pointers = lines.iter().map(|&(ref pointer, _)| pointer.as_ref().map(String::as_str)).collect::<Vec<_>>();
+        assert_eq!(pointers, vec![Some(""), Some("/a"), Some("/a/0"), Some("/a/1"), None, Some("/b"), Some("/b/c~1d"), None, None]);
+    }
+}
@@ -0,0 +1,258 @@
+use actix_web::{actix, error, server, App, Error, FutureResponse, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::http::{ContentEncoding, Cookie, StatusCode, header};
+use atty::{self, Stream};
+use base64;
+use bytes::Bytes;
+use clap::{App as ClapApp, AppSettings, Arg};
+use futures::{future, stream, Future};
+use rand::{self, Rng};
+use serde_json::{self, Map, Value};
+use tokio_timer::Delay;
+use url::form_urlencoded;
+
+use std::io;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use format::{headers_object, json_object};
+
+/// Limits which keep a mistyped number from tying up the server
+static MAX_DELAY_SECONDS: f64 = 10.0;
+static MAX_REDIRECTS: usize = 100;
+static MAX_STREAM_LINES: usize = 100;
+static MAX_BYTES: usize = 100 * 1024;
+static MAX_REQUEST_BODY: usize = 10 * 1024 * 1024;
+
+/// `chrome serve` runs an httpbin-style echo server until interrupted
+pub fn serve(args: Vec<String>) -> io::Result<()> {
+    let colored = atty::is(Stream::Stdout);
+    let matches = ClapApp::new("chrome serve")
+        .about("Run a local echo server, in the style of httpbin, to send requests to")
+        .global_setting(if colored { AppSettings::ColoredHelp } else { AppSettings::ColorNever })
+        .after_help(include_str!("./help/serve.help.txt"))
+        .arg(Arg::with_name("bind")
+             .long("bind")
+             .short("b")
+             .value_name("ADDRESS")
+             .default_value("127.0.0.1:8000")
+             .help("The address to listen on, use port 0 for any free port")
+        )
+        .get_matches_from(args);
+
+    let sys = actix::System::new("chrome serve");
+    let server = server::new(app).bind(matches.value_of("bind").unwrap())?;
+    for addr in server.addrs() {
+        eprintln!("Serving on http://{}", addr);
+    }
+    server.start();
+    sys.run();
+    Ok(())
+}
+
+/// The application behind `chrome serve`, which can also be started on a server of one's own
+pub fn app() -> App {
+    App::new()
+        .resource("/anything", |r| r.f(anything))
+        .resource("/anything/{rest:.*}", |r| r.f(anything))
+        .resource("/status/{code}", |r| r.f(status))
+        .resource("/delay/{seconds}", |r| r.f(delay))
+        .resource("/redirect/{n}", |r| r.f(redirect))
+        .resource("/cookies", |r| r.f(cookies))
+        .resource("/cookies/set", |r| r.f(set_cookies))
+        .resource("/basic-auth/{user}/{password}", |r| r.f(basic_auth))
+        .resource("/stream/{n}", |r| r.f(stream_lines))
+        .resource("/bytes/{n}", |r| r.f(random_bytes))
+        .resource("/gzip", |r| r.f(gzip))
+}
+
+fn anything(req: &HttpRequest) -> FutureResponse<HttpResponse> {
+    let req = req.clone();
+    Box::new(req.body().limit(MAX_REQUEST_BODY).from_err().map(move |body| {
+        let mut info = describe(&req);
+        info.insert(String::from("data"), Value::String(String::from_utf8_lossy(&body).into_owned()));
+        let form = if is_form(&req) {
+            json_object(form_urlencoded::parse(&body).into_owned())
+        } else {
+            Map::new()
+        };
+        info.insert(String::from("form"), Value::Object(form));
+        info.insert(String::from("json"), serde_json::from_slice(&body).unwrap_or(Value::Null));
+        json_response(StatusCode::OK, info)
+    }))
+}
+
+fn status(req: &HttpRequest) -> Result<HttpResponse, Error> {
+    let code = param::<u16>(req, "code")?;
+    let status = StatusCode::from_u16(code)
+        .map_err(|_| error::ErrorBadRequest(format!("{} is not a valid status", code)))?;
+    let mut response = HttpResponse::build(status);
+    if status.is_redirection() {
+        response.header(header::LOCATION, "/anything");
+    }
+    if status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED {
+        // These can't have a body
+        return Ok(response.finish());
+    }
+    let mut info = Map::new();
+    info.insert(String::from("status"), Value::from(code));
+    info.insert(String::from("reason"), Value::from(status.canonical_reason().unwrap_or("")));
+    Ok(json_response_from(response, info))
+}
+
+fn delay(req: &HttpRequest) -> FutureResponse<HttpResponse> {
+    let seconds = match param::<f64>(req, "seconds") {
+        Ok(seconds) => delay_seconds(seconds),
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let req = req.clone();
+    Box::new(Delay::new(Instant::now() + Duration::from_millis((seconds * 1000.0) as u64))
+        .map_err(error::ErrorInternalServerError)
+        .map(move |_| {
+            let mut info = describe(&req);
+            info.insert(String::from("delay"), Value::from(seconds));
+            json_response(StatusCode::OK, info)
+        }))
+}
+
+fn delay_seconds(requested: f64) -> f64 {
+    requested.max(0.0).min(MAX_DELAY_SECONDS)
+}
+
+fn redirect(req: &HttpRequest) -> Result<HttpResponse, Error> {
+    let n = param::<usize>(req, "n")?;
+    if n == 0 || n > MAX_REDIRECTS {
+        return Err(error::ErrorBadRequest(format!("n must be between 1 and {}", MAX_REDIRECTS)));
+    }
+    let location = if n == 1 { String::from("/anything") } else { format!("/redirect/{}", n - 1) };
+    let mut info = Map::new();
+    info.insert(String::from("location"), Value::String(location.clone()));
+    Ok(json_response_from(HttpResponse::Found().header(header::LOCATION, location).take(), info))
+}
+
+fn cookies(req: &HttpRequest) -> Result<HttpResponse, Error> {
+    let cookies = req.cookies()?.iter()
+        .map(|cookie| (String::from(cookie.name()), String::from(cookie.value())))
+        .collect::<Vec<_>>();
+    let mut info = Map::new();
+    info.insert(String::from("cookies"), Value::Object(json_object(cookies)));
+    Ok(json_response(StatusCode::OK, info))
+}
+
+fn set_cookies(req: &HttpRequest) -> HttpResponse {
+    let cookies = form_urlencoded::parse(req.query_string().as_bytes()).into_owned().collect::<Vec<_>>();
+    let mut response = HttpResponse::Found();
+    response.header(header::LOCATION, "/cookies");
+    for &(ref name, ref value) in &cookies {
+        response.cookie(Cookie::build(name.clone(), value.clone()).path("/").finish());
+    }
+    let mut info = Map::new();
+    info.insert(String::from("cookies"), Value::Object(json_object(cookies)));
+    json_response_from(response, info)
+}
+
+fn basic_auth(req: &HttpRequest) -> HttpResponse {
+    let user = req.match_info().get("user").unwrap_or("");
+    let password = req.match_info().get("password").unwrap_or("");
+    let expected = format!("{}:{}", user, password);
+    let authenticated = req.headers().get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            let mut parts = value.splitn(2, ' ');
+            match (parts.next(), parts.next()) {
+                (Some(scheme), Some(credentials)) if scheme.eq_ignore_ascii_case("basic") =>
+                    base64::decode(credentials.trim()).ok(),
+                _ => None,
+            }
+        })
+        .map(|credentials| credentials == expected.as_bytes())
+        .unwrap_or(false);
+
+    let mut info = Map::new();
+    info.insert(String::from("authenticated"), Value::Bool(authenticated));
+    if authenticated {
+        info.insert(String::from("user"), Value::from(user));
+        json_response(StatusCode::OK, info)
+    } else {
+        json_response_from(HttpResponse::Unauthorized()
+                               .header(header::WWW_AUTHENTICATE, "Basic realm=\"chrome serve\"")
+                               .take(),
+                           info)
+    }
+}
+
+fn stream_lines(req: &HttpRequest) -> Result<HttpResponse, Error> {
+    let n = param::<usize>(req, "n")?.min(MAX_STREAM_LINES);
+    let lines = (0..n).map(|id| {
+        let mut info = describe(req);
+        info.insert(String::from("id"), Value::from(id));
+        let mut line = serde_json::to_string(&info).unwrap();
+        line.push('\n');
+        Bytes::from(line)
+    }).collect::<Vec<_>>();
+    Ok(HttpResponse::Ok()
+       .content_type("application/json")
+       .streaming(stream::iter_ok::<_, Error>(lines)))
+}
+
+fn random_bytes(req: &HttpRequest) -> Result<HttpResponse, Error> {
+    let n = param::<usize>(req, "n")?.min(MAX_BYTES);
+    let mut data = vec![0u8; n];
+    rand::thread_rng().fill(&mut data[..]);
+    Ok(HttpResponse::Ok().content_type("application/octet-stream").body(data))
+}
+
+fn gzip(req: &HttpRequest) -> HttpResponse {
+    let mut info = describe(req);
+    info.insert(String::from("gzipped"), Value::Bool(true));
+    json_response_from(HttpResponse::Ok().content_encoding(ContentEncoding::Gzip).take(), info)
+}
+
+/// The parts of a request every endpoint echoes
+fn describe(req: &HttpRequest) -> Map<String, Value> {
+    let url = {
+        let connection = req.connection_info();
+        format!("{}://{}{}", connection.scheme(), connection.host(), req.uri())
+    };
+    let mut info = Map::new();
+    info.insert(String::from("method"), Value::from(req.method().as_str()));
+    info.insert(String::from("url"), Value::String(url));
+    info.insert(String::from("args"),
+                Value::Object(json_object(form_urlencoded::parse(req.query_string().as_bytes()).into_owned())));
+    info.insert(String::from("headers"), Value::Object(headers_object(req.headers())));
+    info.insert(String::from("origin"),
+                req.peer_addr().map(|addr| Value::String(addr.ip().to_string())).unwrap_or(Value::Null));
+    info
+}
+
+fn is_form(req: &HttpRequest) -> bool {
+    req.content_type() == "application/x-www-form-urlencoded"
+}
+
+fn param<T: FromStr>(req: &HttpRequest, name: &str) -> Result<T, Error> {
+    req.match_info().get(name)
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| error::ErrorBadRequest(format!("{} must be a number", name)))
+}
+
+fn json_response(status: StatusCode, info: Map<String, Value>) -> HttpResponse {
+    json_response_from(HttpResponse::build(status), info)
+}
+
+fn json_response_from(mut response: HttpResponseBuilder, info: Map<String, Value>) -> HttpResponse {
+    let mut body = serde_json::to_string_pretty(&info).unwrap();
+    body.push('\n');
+    response.content_type("application/json").body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_are_capped() {
+        assert_eq!(delay_seconds(0.5), 0.5);
+        assert_eq!(delay_seconds(-1.0), 0.0);
+        assert_eq!(delay_seconds(3600.0), 10.0);
+    }
+}
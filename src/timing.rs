@@ -0,0 +1,140 @@
+use ansi_term::Colour::{Blue, Green};
+use serde_json::{Map, Number, Value};
+
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimingFormat {
+    Table,
+    Json,
+}
+
+/// How long each phase of a request took. The TLS handshake is only set for https urls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timing {
+    pub dns: Duration,
+    pub connect: Duration,
+    pub tls: Option<Duration>,
+    pub ttfb: Duration,
+    pub download: Duration,
+}
+
+pub fn is_duration(v: String) -> Result<(), String> {
+    parse_duration(&v).map(|_| ())
+}
+
+/// Parses durations like `500ms`, `30s`, `2m` or `1h`. A bare number is taken as seconds.
+pub fn parse_duration(v: &str) -> Result<Duration, String> {
+    let v = v.trim();
+    let split = v.find(|c: char| !(c.is_digit(10) || c == '.')).unwrap_or(v.len());
+    let (number, unit) = v.split_at(split);
+    let number = number.parse::<f64>()
+        .map_err(|_| format!("Invalid duration '{}'", v))?;
+    let seconds = match unit.trim() {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("Invalid unit in duration '{}', expected ms, s, m or h", v)),
+    };
+    Ok(from_secs_f64(seconds))
+}
+
+pub fn from_secs_f64(seconds: f64) -> Duration {
+    Duration::new(seconds.trunc() as u64, (seconds.fract() * 1_000_000_000.0) as u32)
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + duration.subsec_nanos() as f64 / 1_000_000.0
+}
+
+impl Timing {
+    pub fn total(&self) -> Duration {
+        self.dns + self.connect + self.tls.unwrap_or_default() + self.ttfb + self.download
+    }
+
+    fn phases(&self) -> Vec<(&'static str, Option<Duration>)> {
+        vec![
+            ("dns_lookup", Some(self.dns)),
+            ("tcp_connect", Some(self.connect)),
+            ("tls_handshake", self.tls),
+            ("time_to_first_byte", Some(self.ttfb)),
+            ("download", Some(self.download)),
+            ("total", Some(self.total())),
+        ]
+    }
+
+    /// Every phase in milliseconds, e.g. `{"dns_lookup_ms": 1.2, ...}`
+    pub fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        for (name, duration) in self.phases() {
+            let value = duration
+                .and_then(|d| Number::from_f64(millis(d)))
+                .map(Value::Number)
+                .unwrap_or(Value::Null);
+            map.insert(format!("{}_ms", name), value);
+        }
+        Value::Object(map)
+    }
+
+    /// Prints the timings to stderr, so they don't get mixed up with a piped response body.
+    pub fn print(&self, format: TimingFormat, colored_output: bool) -> io::Result<()> {
+        let stderr = io::stderr();
+        let mut out = stderr.lock();
+        match format {
+            TimingFormat::Json => writeln!(out, "{}", self.to_json()),
+            TimingFormat::Table => {
+                writeln!(out, "")?;
+                for (name, duration) in self.phases() {
+                    let label = format!("{:<20}", name.replace('_', " "));
+                    let value = duration
+                        .map(|d| format!("{:>10.2} ms", millis(d)))
+                        .unwrap_or(format!("{:>13}", "-"));
+                    if colored_output {
+                        writeln!(out, "{}{}", Blue.paint(label), Green.paint(value))?;
+                    } else {
+                        writeln!(out, "{}{}", label, value)?;
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn durations_need_a_number_and_a_known_unit() {
+        assert_eq!(parse_duration("soon"), Err(String::from("Invalid duration 'soon'")));
+        assert_eq!(parse_duration("3d"),
+                   Err(String::from("Invalid unit in duration '3d', expected ms, s, m or h")));
+    }
+
+    #[test]
+    fn json_has_every_phase_in_milliseconds() {
+        let timing = Timing {
+            dns: Duration::from_millis(1),
+            connect: Duration::from_millis(2),
+            tls: None,
+            ttfb: Duration::from_millis(10),
+            download: Duration::from_millis(3),
+        };
+        let json = timing.to_json();
+        assert_eq!(json["dns_lookup_ms"], 1.0);
+        assert_eq!(json["tls_handshake_ms"], Value::Null);
+        assert_eq!(json["total_ms"], 16.0);
+    }
+}
@@ -0,0 +1,73 @@
+extern crate actix_web;
+extern crate chrome;
+extern crate futures;
+extern crate serde_json;
+
+mod common;
+
+use serde_json::Value;
+
+use common::{chrome, serve_url, temp_file};
+
+fn results(stdout: &str) -> Vec<Value> {
+    stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+#[test]
+fn json_lines_in_input_order() {
+    let base = serve_url("");
+    let file = temp_file("requests.jsonl", &format!(
+        "{{\"method\": \"post\", \"url\": \"{0}/anything\", \"items\": [\"name=chrome\", \"X-Tag:a\"]}}\n\
+         \n\
+         {{\"url\": \"{0}/status/404\"}}\n\
+         {{\"url\": \"{0}/anything\", \"items\": [\"X-Empty;value\"]}}\n\
+         not json\n",
+        base));
+    let run = chrome(&["batch", "-c", "3", "--output", "jsonl", file.to_str().unwrap()]);
+
+    assert_eq!(run.status, Some(1));
+    let results = results(&run.stdout);
+    let lines: Vec<u64> = results.iter().map(|r| r["line"].as_u64().unwrap()).collect();
+    assert_eq!(lines, vec![1, 3, 4, 5]);
+
+    assert_eq!(results[0]["method"], "POST");
+    assert_eq!(results[0]["status"], 200);
+    assert_eq!(results[0]["body"]["json"]["name"], "chrome");
+    assert_eq!(results[0]["body"]["headers"]["x-tag"], "a");
+    assert!(results[0]["timing"]["total_ms"].is_number());
+
+    assert_eq!(results[1]["status"], 404);
+    assert!(results[2]["error"].as_str().unwrap().contains("X-Empty;value"));
+    assert!(results[3]["error"].is_string());
+
+    assert!(run.stderr.contains("4 requests: 1 succeeded, 3 failed"), "{}", run.stderr);
+    assert!(run.stderr.contains("line 3: GET "), "{}", run.stderr);
+    assert!(run.stderr.contains("/status/404: 404 Not Found"), "{}", run.stderr);
+}
+
+#[test]
+fn completion_order_prints_fast_responses_first() {
+    let base = serve_url("");
+    let file = temp_file("requests.jsonl", &format!(
+        "{{\"url\": \"{0}/delay/1\"}}\n\
+         {{\"url\": \"{0}/anything\"}}\n",
+        base));
+    let run = chrome(&["batch", "-c", "2", "--order", "completion", "--output", "jsonl", file.to_str().unwrap()]);
+
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+    let lines: Vec<u64> = results(&run.stdout).iter().map(|r| r["line"].as_u64().unwrap()).collect();
+    assert_eq!(lines, vec![2, 1]);
+}
+
+#[test]
+fn pretty_output_shows_each_response() {
+    let base = serve_url("");
+    let file = temp_file("requests.jsonl", &format!("{{\"url\": \"{}/status/201\"}}\n", base));
+    let run = chrome(&["batch", "--output", "pretty", file.to_str().unwrap()]);
+
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+    assert!(run.stdout.starts_with(&format!("#1 GET {}/status/201\n", base)), "{}", run.stdout);
+    assert!(run.stdout.contains("HTTP/1.1 201 Created\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("\"reason\": \"Created\""), "{}", run.stdout);
+    assert!(run.stderr.contains("1 request: 1 succeeded, 0 failed"), "{}", run.stderr);
+}
@@ -0,0 +1,52 @@
+extern crate actix_web;
+extern crate chrome;
+extern crate futures;
+extern crate serde_json;
+
+mod common;
+
+use serde_json::Value;
+
+use common::{chrome, serve_url};
+
+#[test]
+fn json_report_counts_every_response() {
+    let run = chrome(&["bench", "-n", "20", "-c", "4", "--report", "json", "post", &serve_url("/anything"), "name=chrome"]);
+
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+    let report: Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(report["responses"], 20);
+    assert_eq!(report["errors"], 0);
+    assert_eq!(report["status_codes"], serde_json::from_str::<Value>(r#"{"200": 20}"#).unwrap());
+    let latency = &report["latency"];
+    assert!(latency["p50_ms"].as_f64().unwrap() <= latency["max_ms"].as_f64().unwrap());
+}
+
+#[test]
+fn table_report_lists_status_codes() {
+    let run = chrome(&["bench", "-n", "6", "-c", "2", "get", &serve_url("/status/503")]);
+
+    // Unsuccessful statuses are still responses rather than errors
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+    assert!(run.stdout.contains("Responses     6 in "), "{}", run.stdout);
+    assert!(run.stdout.contains("Status codes  503: 6"), "{}", run.stdout);
+    assert!(run.stdout.contains("Errors        0"), "{}", run.stdout);
+}
+
+#[test]
+fn connection_errors_are_counted() {
+    let run = chrome(&["bench", "-n", "3", "-c", "1", "--report", "json", "get", "http://127.0.0.1:1/"]);
+
+    assert_eq!(run.status, Some(1));
+    let report: Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(report["responses"], 0);
+    assert_eq!(report["errors"], 3);
+}
+
+#[test]
+fn http2_is_turned_down() {
+    let run = chrome(&["bench", "--http2", "get", "https://127.0.0.1:1/"]);
+
+    assert_eq!(run.status, Some(1));
+    assert!(run.stderr.contains("chrome bench only speaks HTTP/1.1"), "{}", run.stderr);
+}
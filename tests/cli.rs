@@ -0,0 +1,350 @@
+extern crate actix_web;
+extern crate chrome;
+extern crate futures;
+extern crate serde_json;
+
+mod common;
+
+use serde_json::Value;
+
+use std::fs;
+
+use common::{MockServer, chrome, chrome_with_stdin, temp_dir, temp_file};
+
+fn json(s: &str) -> Value {
+    serde_json::from_str(s).unwrap()
+}
+
+#[test]
+fn get_without_items() {
+    let server = MockServer::start();
+    let run = chrome(&["get", &server.url("/users")]);
+
+    assert_eq!(run.status, Some(0));
+    let received = server.received();
+    assert_eq!(received.method, "GET");
+    assert_eq!(received.path, "/users");
+    assert!(received.body.is_empty());
+    assert!(received.header("User-Agent").unwrap().starts_with("chrome/"));
+}
+
+#[test]
+fn data_fields_are_sent_as_json() {
+    let server = MockServer::start();
+    let run = chrome(&["post", &server.url("/users"), "name=chrome", "count:=5", "tags:=[\"a\", \"b\"]"]);
+
+    assert_eq!(run.status, Some(0));
+    let received = server.received();
+    assert_eq!(received.method, "POST");
+    assert_eq!(received.header("Content-Type"), Some("application/json"));
+    // Fields keep the order they were given in
+    assert_eq!(received.body_str(), r#"{"name":"chrome","count":5,"tags":["a","b"]}"#);
+}
+
+#[test]
+fn repeated_json_keys_become_arrays() {
+    let server = MockServer::start();
+    chrome(&["post", &server.url("/"), "tag=a", "tag=b"]);
+
+    assert_eq!(json(server.received().body_str()), json(r#"{"tag": ["a", "b"]}"#));
+}
+
+#[test]
+fn data_fields_are_sent_as_a_form() {
+    let server = MockServer::start();
+    let run = chrome(&["--form", "post", &server.url("/"), "name=chrome rs", "tag=a", "tag=b"]);
+
+    assert_eq!(run.status, Some(0));
+    let received = server.received();
+    assert_eq!(received.header("Content-Type"), Some("application/x-www-form-urlencoded"));
+    assert_eq!(received.body_str(), "name=chrome+rs&tag=a&tag=b");
+}
+
+#[test]
+fn url_parameters_are_appended_to_the_query() {
+    let server = MockServer::start();
+    chrome(&["get", &server.url("/search?lang=en"), "q==rust http", "page==2", "q==again"]);
+
+    let received = server.received();
+    assert_eq!(received.path, "/search");
+    assert_eq!(received.query, "lang=en&q=rust+http&page=2&q=again");
+}
+
+#[test]
+fn headers_can_be_set_unset_and_sent_empty() {
+    let server = MockServer::start();
+    chrome(&["get", &server.url("/"), "X-Api-Key:secret", "X-Tag:a", "X-Tag:b", "User-Agent:", "X-Empty;"]);
+
+    let received = server.received();
+    assert_eq!(received.header("X-Api-Key"), Some("secret"));
+    assert_eq!(received.header("User-Agent"), None);
+    assert_eq!(received.header("X-Empty"), Some(""));
+    let tags: Vec<&str> = received.headers.iter()
+        .filter(|&&(ref key, _)| key == "x-tag")
+        .map(|&(_, ref value)| value.as_str())
+        .collect();
+    assert_eq!(tags, vec!["a", "b"]);
+}
+
+#[test]
+fn file_items_embed_file_contents() {
+    let server = MockServer::start();
+    let essay = temp_file("essay.txt", "A long\nessay\n");
+    let package = temp_file("package.json", r#"{"name": "chrome"}"#);
+    let token = temp_file("token", "s3cret\n");
+    chrome(&[
+        "post", &server.url("/"),
+        &format!("essay=@{}", essay.display()),
+        &format!("package:=@{}", package.display()),
+        &format!("Authorization:@{}", token.display()),
+    ]);
+
+    let received = server.received();
+    assert_eq!(json(received.body_str()), json(r#"{"essay": "A long\nessay\n", "package": {"name": "chrome"}}"#));
+    // The trailing newline is trimmed from header values
+    assert_eq!(received.header("Authorization"), Some("s3cret"));
+}
+
+#[test]
+fn url_parameter_from_stdin() {
+    let server = MockServer::start();
+    chrome_with_stdin(&["get", &server.url("/"), "signature==@-"], "abc123\n");
+
+    assert_eq!(server.received().query, "signature=abc123");
+}
+
+#[test]
+fn body_file_is_sent_as_is_with_a_guessed_content_type() {
+    let server = MockServer::start();
+    let body = temp_file("request.xml", "<request id=\"1\"/>");
+    chrome(&["post", &server.url("/"), &format!("@{}", body.display())]);
+
+    let received = server.received();
+    assert_eq!(received.header("Content-Type"), Some("application/xml"));
+    assert_eq!(received.body_str(), "<request id=\"1\"/>");
+}
+
+#[test]
+fn raw_body_with_a_content_type_header() {
+    let server = MockServer::start();
+    chrome(&["post", &server.url("/graphql"), "--raw", "{ viewer { login } }", "Content-Type:application/graphql"]);
+
+    let received = server.received();
+    assert_eq!(received.header("Content-Type"), Some("application/graphql"));
+    assert_eq!(received.body_str(), "{ viewer { login } }");
+}
+
+#[test]
+fn json_and_form_cannot_be_combined() {
+    let run = chrome(&["post", "http://127.0.0.1:1/", "--json", "--form", "name=chrome"]);
+
+    assert_eq!(run.status, Some(1));
+    assert!(run.stderr.contains("cannot be used with"), "{}", run.stderr);
+}
+
+#[test]
+fn raw_body_cannot_be_combined_with_data_fields() {
+    let run = chrome(&["post", "http://127.0.0.1:1/", "--raw", "{}", "name=chrome"]);
+
+    assert_eq!(run.status, Some(1));
+    assert!(run.stderr.contains("can't be combined"), "{}", run.stderr);
+}
+
+#[test]
+fn escaped_separators_are_part_of_the_key() {
+    let server = MockServer::start();
+    chrome(&["post", &server.url("/"), "field\\:name=value", "a\\=b==c"]);
+
+    let received = server.received();
+    assert_eq!(json(received.body_str()), json(r#"{"field:name": "value"}"#));
+    assert_eq!(received.query, "a%3Db=c");
+}
+
+#[test]
+fn invalid_items_are_reported_with_a_caret() {
+    let run = chrome(&["get", "http://127.0.0.1:1/", "X-Empty;value"]);
+
+    assert_ne!(run.status, Some(0));
+    assert!(run.stderr.contains("X-Empty;value"), "{}", run.stderr);
+    assert!(run.stderr.contains("        ^"), "{}", run.stderr);
+    assert!(run.stderr.contains("use `X-Empty:value`"), "{}", run.stderr);
+}
+
+#[test]
+fn non_tty_output_is_the_plain_body() {
+    let server = MockServer::start();
+    let run = chrome(&["get", &server.url("/")]);
+
+    assert_eq!(run.status, Some(0));
+    assert!(!run.stdout.contains('\x1b'), "{:?}", run.stdout);
+    assert!(!run.stdout.contains("HTTP/1.1"), "{:?}", run.stdout);
+    assert_eq!(run.stdout, "{\n  \"ok\": true\n}\n");
+}
+
+#[test]
+fn colour_can_be_forced_without_a_tty() {
+    let server = MockServer::start();
+    let run = chrome(&["--color", "always", "get", &server.url("/")]);
+
+    assert!(run.stdout.contains("\x1b["), "{:?}", run.stdout);
+}
+
+#[test]
+fn verbose_output_shows_the_request_and_response() {
+    let server = MockServer::start();
+    let run = chrome(&["-v", "get", &server.url("/text"), "q==1"]);
+
+    assert!(run.stdout.starts_with("GET /text?q=1 HTTP/1.1\n"), "{:?}", run.stdout);
+    assert!(run.stdout.contains("HTTP/1.1 200 OK\n"), "{:?}", run.stdout);
+    assert!(run.stdout.contains("plain text"), "{:?}", run.stdout);
+}
+
+#[test]
+fn unsuccessful_status_exits_with_1() {
+    let server = MockServer::start();
+    let run = chrome(&["get", &server.url("/status/404")]);
+
+    assert_eq!(run.status, Some(1));
+    assert_eq!(json(&run.stdout), json(r#"{"status": 404}"#));
+}
+
+#[test]
+fn filtered_strings_are_unquoted_when_piped() {
+    let server = MockServer::start();
+    let run = chrome(&["get", &server.url("/"), "--filter", "keys | .[0]"]);
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+    assert_eq!(run.stdout, "ok\n");
+
+    let run = chrome(&["get", &server.url("/"), "--filter", "keys"]);
+    assert_eq!(run.stdout, "[\n  \"ok\"\n]\n");
+}
+
+#[test]
+fn met_expectations_decide_the_exit_status() {
+    let server = MockServer::start();
+    let run = chrome(&["get", &server.url("/status/404"), "--expect", "status=404", "--expect", "body.status exists"]);
+
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+    assert!(run.stderr.contains("All 2 expectations passed"), "{}", run.stderr);
+}
+
+#[test]
+fn failed_expectations_exit_with_4() {
+    let server = MockServer::start();
+    let run = chrome(&["get", &server.url("/"), "--expect", "status=201", "--expect", "body.ok=true",
+                       "--expect", "header.Content-Type~xml"]);
+
+    assert_eq!(run.status, Some(4));
+    assert!(run.stderr.contains("Expectation failed: status=201\n  - expected  status = 201\n  + actual    status = 200\n"),
+            "{}", run.stderr);
+    assert!(run.stderr.contains("Expectation failed: header.Content-Type~xml"), "{}", run.stderr);
+    assert!(!run.stderr.contains("body.ok=true"), "{}", run.stderr);
+    assert!(run.stderr.contains("2 of 3 expectations failed"), "{}", run.stderr);
+}
+
+#[test]
+fn responses_are_checked_against_a_schema_with_local_refs() {
+    let server = MockServer::start();
+    let dir = temp_dir();
+    fs::write(dir.join("defs.json"), r#"{"$defs": {"text": {"type": "string"}, "flag": {"type": "boolean"}}}"#).unwrap();
+    fs::write(dir.join("ok.json"), r#"{"properties": {"ok": {"$ref": "defs.json#/$defs/flag"}}}"#).unwrap();
+    fs::write(dir.join("user.json"), r#"{"properties": {"ok": {"$ref": "defs.json#/$defs/text"}}, "required": ["id"]}"#).unwrap();
+
+    let run = chrome(&["get", &server.url("/"), "--response-schema", dir.join("ok.json").to_str().unwrap()]);
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+
+    let run = chrome(&["get", &server.url("/"), "--response-schema", dir.join("user.json").to_str().unwrap()]);
+    assert_eq!(run.status, Some(4));
+    assert!(run.stderr.contains("  /ok: expected string, got boolean\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("  (root): missing required property 'id'\n"), "{}", run.stderr);
+    assert!(run.stderr.contains("> 1 | {\n> 2 |   \"ok\": true\n  3 | }\n"), "{}", run.stderr);
+}
+
+#[test]
+fn requests_which_dont_match_their_schema_are_not_sent() {
+    let schema = temp_file("schema.json", r#"{"properties": {"count": {"type": "integer", "minimum": 1}}}"#);
+    // Nothing listens on port 1, so getting past the schema would fail with 1
+    let run = chrome(&["post", "http://127.0.0.1:1/", "count:=0", "--request-schema", schema.to_str().unwrap()]);
+
+    assert_eq!(run.status, Some(4));
+    assert!(run.stderr.contains("The request body doesn't match"), "{}", run.stderr);
+    assert!(run.stderr.contains("/count: expected at least 1, got 0"), "{}", run.stderr);
+}
+
+#[test]
+fn recorded_cassettes_are_replayed_without_the_network() {
+    let server = MockServer::start();
+    let dir = temp_dir();
+    let dir = dir.to_str().unwrap();
+    let url = server.url("/status/201");
+
+    let recorded = chrome(&["post", &url, "name=chrome", "--record", dir]);
+    assert_eq!(recorded.status, Some(0));
+    assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+
+    let replayed = chrome(&["-v", "post", &url, "name=chrome", "--replay", dir]);
+    assert_eq!(replayed.status, Some(0));
+    assert!(replayed.stdout.contains("HTTP/1.1 201 Created\n"), "{}", replayed.stdout);
+    assert!(replayed.stdout.ends_with(&recorded.stdout), "{}", replayed.stdout);
+    // Only the recording reached the server
+    server.received();
+
+    let other = chrome(&["post", &url, "name=other", "--replay", dir]);
+    assert_eq!(other.status, Some(1));
+    assert!(other.stderr.contains("No cassette matches POST"), "{}", other.stderr);
+
+    let loose = chrome(&["post", &url, "name=other", "--replay", dir, "--match-on", "method,url"]);
+    assert_eq!(loose.stdout, recorded.stdout);
+}
+
+#[test]
+fn cassettes_leave_out_secrets() {
+    let server = MockServer::start();
+    let dir = temp_dir();
+    let url = server.url("/");
+
+    let run = chrome(&[
+        "get", &url, "Authorization:Bearer hunter2", "X-Api-Key:secret", "Accept:text/plain",
+        "--record", dir.to_str().unwrap(), "--redact", "x-api-key",
+    ]);
+    assert_eq!(run.status, Some(0));
+    let entry = fs::read_dir(&dir).unwrap().next().unwrap().unwrap();
+    let cassette = fs::read_to_string(entry.path()).unwrap();
+
+    assert!(!cassette.contains("hunter2"), "{}", cassette);
+    assert!(!cassette.contains("secret"), "{}", cassette);
+    assert!(cassette.contains("text/plain"), "{}", cassette);
+    assert!(!cassette.contains("content-length"), "{}", cassette);
+}
+
+#[test]
+fn redacted_headers_stay_out_of_cassette_names() {
+    let server = MockServer::start();
+    let dir = temp_dir();
+    let url = server.url("/");
+
+    for token in &["Authorization:Bearer hunter2", "Authorization:Bearer hunter3"] {
+        let run = chrome(&["get", &url, token, "--record", dir.to_str().unwrap(), "--match-on", "url,header:Authorization"]);
+        assert_eq!(run.status, Some(0));
+    }
+
+    // Both requests look the same once the token is redacted, so they share a cassette
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+}
+
+#[test]
+fn match_on_needs_a_cassette_directory() {
+    let run = chrome(&["get", "http://127.0.0.1:1/", "--match-on", "method,url"]);
+
+    assert_eq!(run.status, Some(1));
+    assert!(run.stderr.contains("--match-on"), "{}", run.stderr);
+}
+
+#[test]
+fn explain_describes_items() {
+    let run = chrome(&["explain", "count=5"]);
+
+    assert_eq!(run.status, Some(0));
+    assert!(run.stdout.contains("the field count of the body"), "{}", run.stdout);
+    assert!(run.stdout.contains("did you mean `count:=5` for a number?"), "{}", run.stdout);
+}
@@ -0,0 +1,200 @@
+//! A local server which records every request it receives, and helpers to run the `chrome`
+//! binary against it.
+
+#![allow(dead_code)]
+
+use actix_web::{actix, server, App, HttpMessage, HttpRequest, HttpResponse};
+use actix_web::http::StatusCode;
+use actix_web::server::IntoHttpHandler;
+use futures::Future;
+
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::io::Write;
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::thread;
+
+/// A request as the server saw it
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Received {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+
+    pub fn body_str(&self) -> &str {
+        ::std::str::from_utf8(&self.body).unwrap()
+    }
+}
+
+/// Responds to
+///
+///  - `/status/CODE` with that status and a short JSON body
+///  - `/text` with a plain text body
+///  - anything else with `{"ok": true}`
+pub struct MockServer {
+    addr: SocketAddr,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl MockServer {
+    /// Starts a server on an ephemeral port, on its own thread
+    pub fn start() -> MockServer {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let shared = received.clone();
+        let addr = start_server(move || {
+            let shared = shared.clone();
+            App::new().default_resource(move |r| {
+                let shared = shared.clone();
+                r.f(move |req: &HttpRequest| respond(req, shared.clone()))
+            })
+        });
+
+        MockServer {
+            addr: addr,
+            received: received,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// The only request received so far
+    pub fn received(&self) -> Received {
+        let received = self.received.lock().unwrap();
+        assert_eq!(received.len(), 1, "expected exactly one request, got {:?}", *received);
+        received[0].clone()
+    }
+}
+
+/// Starts a server for the applications made by `factory` on an ephemeral port, on its own
+/// thread, and returns its address
+pub fn start_server<F, H>(factory: F) -> SocketAddr
+    where F: Fn() -> H + Send + Clone + 'static,
+          H: IntoHttpHandler + 'static
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let sys = actix::System::new("test-server");
+        let server = server::new(factory)
+            .workers(1)
+            .disable_signals()
+            .bind("127.0.0.1:0")
+            .unwrap();
+        tx.send(server.addrs()[0]).unwrap();
+        server.start();
+        sys.run();
+    });
+    rx.recv().unwrap()
+}
+
+/// The url of `path` on a new server running the `chrome serve` app
+pub fn serve_url(path: &str) -> String {
+    format!("http://{}{}", start_server(::chrome::serve_app), path)
+}
+
+fn respond(req: &HttpRequest, shared: Arc<Mutex<Vec<Received>>>) -> Box<Future<Item = HttpResponse, Error = ::actix_web::Error>> {
+    let mut received = Received {
+        method: String::from(req.method().as_str()),
+        path: String::from(req.path()),
+        query: String::from(req.query_string()),
+        headers: req.headers().iter()
+            .map(|(key, value)| (String::from(key.as_str()), String::from(value.to_str().unwrap_or(""))))
+            .collect(),
+        body: Vec::new(),
+    };
+    let path = String::from(req.path());
+
+    Box::new(req.body().from_err().map(move |body| {
+        received.body = body.to_vec();
+        shared.lock().unwrap().push(received);
+
+        if path.starts_with("/status/") {
+            let code = path["/status/".len()..].parse().unwrap_or(500);
+            HttpResponse::build(StatusCode::from_u16(code).unwrap())
+                .content_type("application/json")
+                .body(format!("{{\"status\": {}}}", code))
+        } else if path == "/text" {
+            HttpResponse::Ok().content_type("text/plain").body("plain text\n")
+        } else {
+            HttpResponse::Ok().content_type("application/json").body("{\"ok\": true}")
+        }
+    }))
+}
+
+/// What running `chrome` produced
+pub struct Run {
+    pub status: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Run {
+    fn from(output: Output) -> Run {
+        Run {
+            status: output.status.code(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+}
+
+static SUBCOMMANDS: &[&str] = &["batch", "bench", "explain", "serve"];
+
+/// Runs `chrome` with `args`, without any config file or colour settings from the environment.
+/// Its stdout is a pipe, so it behaves as when it isn't writing to a terminal.
+pub fn chrome(args: &[&str]) -> Run {
+    chrome_with_stdin(args, "")
+}
+
+pub fn chrome_with_stdin(args: &[&str], stdin: &str) -> Run {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_chrome"));
+    // Subcommands have to come first, and don't read the config file anyway
+    if !args.first().map(|arg| SUBCOMMANDS.contains(arg)).unwrap_or(false) {
+        command.arg("--no-config");
+    }
+    let mut child = command
+        .args(args)
+        .env_remove("NO_COLOR")
+        .env_remove("FORCE_COLOR")
+        .env_remove("CHROME_PAGER")
+        .env("TERM", "xterm-256color")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    Run::from(child.wait_with_output().unwrap())
+}
+
+static TEMP_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Makes a new, empty directory in the temp directory
+pub fn temp_dir() -> PathBuf {
+    let dir = env::temp_dir().join(format!("chrome-tests-{}-{}", ::std::process::id(),
+                                           TEMP_COUNTER.fetch_add(1, Ordering::SeqCst)));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes `contents` to a new file in the temp directory, with the given file name
+pub fn temp_file(name: &str, contents: &str) -> PathBuf {
+    let path = temp_dir().join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
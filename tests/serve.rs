@@ -0,0 +1,145 @@
+extern crate actix_web;
+extern crate chrome;
+extern crate futures;
+extern crate serde_json;
+
+mod common;
+
+use serde_json::Value;
+
+use common::{chrome, serve_url};
+
+fn json(s: &str) -> Value {
+    serde_json::from_str(s).unwrap()
+}
+
+#[test]
+fn anything_echoes_the_request() {
+    let run = chrome(&["post", &serve_url("/anything/users?page=2"), "name=chrome", "X-Api-Key:secret"]);
+
+    assert_eq!(run.status, Some(0));
+    let echo = json(&run.stdout);
+    assert_eq!(echo["method"], "POST");
+    assert!(echo["url"].as_str().unwrap().ends_with("/anything/users?page=2"));
+    assert_eq!(echo["args"], json(r#"{"page": "2"}"#));
+    assert_eq!(echo["headers"]["x-api-key"], "secret");
+    assert_eq!(echo["json"], json(r#"{"name": "chrome"}"#));
+    assert_eq!(echo["origin"], "127.0.0.1");
+}
+
+#[test]
+fn anything_echoes_forms() {
+    let run = chrome(&["--form", "put", &serve_url("/anything"), "tag=a", "tag=b"]);
+
+    let echo = json(&run.stdout);
+    assert_eq!(echo["data"], "tag=a&tag=b");
+    assert_eq!(echo["form"], json(r#"{"tag": ["a", "b"]}"#));
+    assert_eq!(echo["json"], Value::Null);
+}
+
+#[test]
+fn status_responds_with_the_code() {
+    let run = chrome(&["get", &serve_url("/status/418")]);
+
+    assert_eq!(run.status, Some(1));
+    assert_eq!(json(&run.stdout), json(r#"{"status": 418, "reason": "I'm a teapot"}"#));
+}
+
+#[test]
+fn redirect_points_at_the_next_hop() {
+    let run = chrome(&["-v", "get", &serve_url("/redirect/3")]);
+
+    assert!(run.stdout.contains("HTTP/1.1 302 Found\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("location: /redirect/2\n"), "{}", run.stdout);
+}
+
+#[test]
+fn basic_auth_checks_the_credentials() {
+    let protected = serve_url("/basic-auth/user/secret");
+
+    let run = chrome(&["get", &protected, "Authorization:Basic dXNlcjpzZWNyZXQ="]);
+    assert_eq!(run.status, Some(0));
+    assert_eq!(json(&run.stdout), json(r#"{"authenticated": true, "user": "user"}"#));
+
+    let run = chrome(&["get", &protected, "Authorization:Basic dXNlcjp3cm9uZw=="]);
+    assert_eq!(run.status, Some(1));
+    assert_eq!(json(&run.stdout), json(r#"{"authenticated": false}"#));
+}
+
+#[test]
+fn gzip_is_decompressed() {
+    let run = chrome(&["-v", "get", &serve_url("/gzip")]);
+
+    assert!(run.stdout.contains("content-encoding: gzip\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("\"gzipped\": true"), "{}", run.stdout);
+}
+
+#[test]
+fn stream_sends_one_line_per_item() {
+    let run = chrome(&["get", &serve_url("/stream/3")]);
+
+    let ids: Vec<Value> = run.stdout.lines().map(|line| json(line)["id"].clone()).collect();
+    assert_eq!(ids, vec![Value::from(0), Value::from(1), Value::from(2)]);
+}
+
+#[test]
+fn bytes_sends_that_many_bytes() {
+    // Without Accept-Encoding the server leaves the body as it is
+    let run = chrome(&["-v", "get", &serve_url("/bytes/16"), "Accept-Encoding:"]);
+
+    assert_eq!(run.status, Some(0));
+    assert!(run.stdout.contains("content-length: 16\n"), "{}", run.stdout);
+}
+
+#[test]
+fn bytes_are_capped_at_100_kib() {
+    let run = chrome(&["-v", "get", &serve_url("/bytes/1000000"), "Accept-Encoding:"]);
+
+    assert_eq!(run.status, Some(0));
+    assert!(run.stdout.contains("content-length: 102400\n"), "{}", run.stdout);
+}
+
+#[test]
+fn delay_waits_before_responding() {
+    let run = chrome(&["get", &serve_url("/delay/0.2")]);
+
+    assert_eq!(run.status, Some(0));
+    assert_eq!(json(&run.stdout)["delay"], 0.2);
+}
+
+#[test]
+fn redirects_are_capped_at_100() {
+    let run = chrome(&["get", &serve_url("/redirect/101")]);
+
+    assert_eq!(run.status, Some(1));
+    assert!(run.stdout.contains("n must be between 1 and 100"), "{}", run.stdout);
+}
+
+#[test]
+fn streams_are_capped_at_100_lines() {
+    let run = chrome(&["get", &serve_url("/stream/1000")]);
+
+    assert_eq!(run.status, Some(0));
+    assert_eq!(run.stdout.lines().count(), 100);
+}
+
+#[test]
+fn cookies_are_set_and_echoed() {
+    let run = chrome(&["-v", "get", &serve_url("/cookies/set?flavour=oat&count=2")]);
+
+    assert!(run.stdout.contains("HTTP/1.1 302 Found\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("location: /cookies\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("set-cookie: flavour=oat; Path=/\n"), "{}", run.stdout);
+    assert!(run.stdout.contains("set-cookie: count=2; Path=/\n"), "{}", run.stdout);
+
+    let run = chrome(&["get", &serve_url("/cookies"), "Cookie:flavour=oat; count=2"]);
+    assert_eq!(run.status, Some(0));
+    assert_eq!(json(&run.stdout), json(r#"{"cookies": {"flavour": "oat", "count": "2"}}"#));
+}
+
+#[test]
+fn expectations_dont_see_binary_bodies_as_text() {
+    let run = chrome(&["get", &serve_url("/bytes/256"), "--expect", "body!~\u{fffd}"]);
+
+    assert_eq!(run.status, Some(0), "{}", run.stderr);
+}